@@ -1,33 +1,806 @@
 use anyhow::{Context, Result};
-use chrono::Datelike;
+use chrono::{Datelike, Timelike};
+use std::collections::{hash_map::Entry, HashMap};
 use tuikit::prelude::*;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 const MAX_SEARCH_RESULT_DEFAULT: usize = 5;
+const JUMP_LIMIT_DEFAULT: usize = 366;
+const MONTHS_DEFAULT: usize = 3;
+/// columns the week numbers in front of each month take up
+const WEEK_COLUMN_WIDTH: usize = 3;
+/// columns one month takes up (incl. the week numbers), and the space between months
+const MONTH_WIDTH: usize = WEEK_COLUMN_WIDTH + 28;
+const MONTH_GAP: usize = 2;
+/// rows one month takes up (incl. the one between rows of months)
+const MONTH_HEIGHT: usize = 9;
+/// --pick=month shows three years, --pick=year this many years at once
+const YEAR_GRID_YEARS: i32 = 20;
+const TIME_STEP_DEFAULT: u32 = 15;
+const SPRINT_LENGTH_DEFAULT: i64 = 14;
+const SPRINT_LENGTH_MAX: i64 = 366;
+/// how often we check --highlight-file / --watch files for changes,
+/// --refresh-interval and whether midnight has passed
+const WATCH_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// foreground colours for --highlight-command categories
+const CATEGORY_COLORS: [Color; 6] = [
+    Color::MAGENTA,
+    Color::YELLOW,
+    Color::GREEN,
+    Color::LIGHT_BLUE,
+    Color::LIGHT_MAGENTA,
+    Color::LIGHT_GREEN,
+];
+/// background colours for the --heatmap shading, from 'a little' to 'a lot'
+const HEAT_COLORS: [Color; 4] = [
+    Color::AnsiValue(22),
+    Color::AnsiValue(28),
+    Color::AnsiValue(34),
+    Color::AnsiValue(40),
+];
+
+/// A --search command, optionally with a name (--search-named=name:command)
+pub struct Search {
+    pub name: Option<String>,
+    pub cmd: String,
+}
+
+impl Search {
+    pub fn new(cmd: &str) -> Search {
+        Search {
+            name: None,
+            cmd: cmd.to_string(),
+        }
+    }
+
+    /// 'name:command' - everything up to the first ':' is the name.
+    /// Only for --search-named, so commands containing a ':' keep working with --search
+    pub fn named(arg: &str) -> Option<Search> {
+        match arg.split_once(':') {
+            Some((name, cmd)) if !name.is_empty() => Some(Search {
+                name: Some(name.to_string()),
+                cmd: cmd.to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The dates a search command can refer to via placeholders
+pub struct SearchDates {
+    pub date: chrono::NaiveDate,
+    pub default_date: chrono::NaiveDate,
+    pub range_start: chrono::NaiveDate,
+    pub range_end: chrono::NaiveDate,
+    /// --tz, for FDATE_TODAY
+    pub timezone: Option<chrono_tz::Tz>,
+}
+
+impl SearchDates {
+    pub fn new(
+        date: chrono::NaiveDate,
+        default_date: chrono::NaiveDate,
+        (range_start, range_end): (chrono::NaiveDate, chrono::NaiveDate),
+        timezone: Option<chrono_tz::Tz>,
+    ) -> SearchDates {
+        SearchDates {
+            date,
+            default_date,
+            range_start,
+            range_end,
+            timezone,
+        }
+    }
+
+    /// Environment variables passed to the search commands,
+    /// so wrapper scripts don't have to parse the date themselves
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let today = today(self.timezone);
+        vec![
+            ("FDATE_DATE", self.date.format("%Y-%m-%d").to_string()),
+            ("FDATE_WEEKDAY", self.date.format("%a").to_string()),
+            ("FDATE_ISOWEEK", self.date.format("%V").to_string()),
+            ("FDATE_ISOYEAR", self.date.format("%G").to_string()),
+            (
+                "FDATE_DEFAULT_DATE",
+                self.default_date.format("%Y-%m-%d").to_string(),
+            ),
+            ("FDATE_TODAY", today.format("%Y-%m-%d").to_string()),
+        ]
+    }
+}
+
+/// Replace the placeholders in `template`:
+/// {} - the date as YYYY-MM-DD
+/// {%...} - the date, formated with a strftime format string, e.g. {%d.%m.%Y}
+/// {year}, {month}, {day}, {week} (ISO), {weekday}
+/// {range_start}, {range_end} - first / last visible date as YYYY-MM-DD
+///
+/// Unknown placeholders are left alone.
+/// Returns the replaced string and whether any placeholder was found.
+pub fn replace_placeholders(template: &str, dates: &SearchDates) -> (String, bool) {
+    use std::fmt::Write;
+    let mut result = String::new();
+    let mut found = false;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+        let name = &rest[1..end];
+        let (date, format) = match name {
+            "" => (dates.date, "%Y-%m-%d"),
+            "year" => (dates.date, "%Y"),
+            "month" => (dates.date, "%m"),
+            "day" => (dates.date, "%d"),
+            "week" => (dates.date, "%V"),
+            "weekday" => (dates.date, "%a"),
+            "range_start" => (dates.range_start, "%Y-%m-%d"),
+            "range_end" => (dates.range_end, "%Y-%m-%d"),
+            _ if name.starts_with('%') => (dates.date, name),
+            _ => {
+                result.push('{');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let mut formated = String::new();
+        //invalid format strings are an error, not a panic, with write!
+        if write!(formated, "{}", date.format(format)).is_ok() {
+            result.push_str(&formated);
+            found = true;
+        } else {
+            result.push_str(&rest[..=end]);
+        }
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    (result, found)
+}
 
 pub fn parse_to_arguments_replace(
     cmd: &str,
-    replacement: Option<&str>,
+    replacement: Option<&SearchDates>,
     add_if_not_found: bool,
 ) -> Vec<String> {
     let mut parsed = shell_words::split(cmd).expect("failed to parse search_cmd");
     if let Some(replacement) = replacement {
-        let hit = parsed
-            .iter()
-            .enumerate()
-            .find(|(_, x)| x == &"{}")
-            .map(|x| x.0);
-        match hit {
-            Some(x) => {
-                parsed[x] = replacement.to_string();
+        let mut any_found = false;
+        for arg in parsed.iter_mut() {
+            let (replaced, found) = replace_placeholders(arg, replacement);
+            if found {
+                *arg = replaced;
+                any_found = true;
             }
-            None => {
-                if add_if_not_found {
-                    parsed.push(replacement.to_string());
+        }
+        if !any_found && add_if_not_found {
+            parsed.push(replacement.date.format("%Y-%m-%d").to_string());
+        }
+    }
+    parsed
+}
+
+/// Like parse_to_arguments_replace, but run the whole command through 'sh -c',
+/// so that pipes and redirections work. Instead of being appended (which would hand
+/// it to the last command of a pipeline), the date is passed as $1,
+/// the first and last visible day as $2 and $3.
+pub fn shell_arguments_replace(cmd: &str, replacement: &SearchDates) -> Vec<String> {
+    vec![
+        "sh".to_string(),
+        "-c".to_string(),
+        replace_placeholders(cmd, replacement).0,
+        "fdate".to_string(),
+        replacement.date.format("%Y-%m-%d").to_string(),
+        replacement.range_start.format("%Y-%m-%d").to_string(),
+        replacement.range_end.format("%Y-%m-%d").to_string(),
+    ]
+}
+
+/// Split a line with ANSI escape sequences into text segments and their attributes.
+/// SGR (colour / effect) sequences are interpreted, all other escape sequences
+/// (incl. OSC ones such as hyperlinks) and control characters are dropped.
+/// Tabs are expanded to spaces, with tab stops every 8 columns.
+pub fn parse_ansi(line: &str) -> Vec<(Attr, String)> {
+    let mut segments = Vec::new();
+    let mut attr = Attr::default();
+    let mut text = String::new();
+    //the column we're at, for the tab stops
+    let mut col = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\t' {
+            let spaces = 8 - col % 8;
+            text.push_str(&" ".repeat(spaces));
+            col += spaces;
+            continue;
+        }
+        if c != '\x1b' {
+            //tuikit would print control characters, and count them as two columns
+            if !c.is_control() {
+                text.push(c);
+                col += c.width().unwrap_or(0);
+            }
+            continue;
+        }
+        if chars.peek() == Some(&']') {
+            //OSC - up to BEL or ESC \
+            while let Some(c) = chars.next() {
+                if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                    break;
                 }
             }
+            continue;
+        }
+        if chars.peek() != Some(&'[') {
+            //not a CSI sequence - drop the escape and the following char
+            chars.next();
+            continue;
+        }
+        chars.next();
+        let mut params = String::new();
+        let mut final_byte = None;
+        for c in chars.by_ref() {
+            if ('@'..='~').contains(&c) {
+                final_byte = Some(c);
+                break;
+            }
+            params.push(c);
+        }
+        if final_byte != Some('m') {
+            continue;
         }
+        if !text.is_empty() {
+            segments.push((attr, std::mem::take(&mut text)));
+        }
+        attr = apply_sgr(attr, &params);
+    }
+    if !text.is_empty() {
+        segments.push((attr, text));
+    }
+    segments
+}
+
+fn apply_sgr(mut attr: Attr, params: &str) -> Attr {
+    let mut codes = params
+        .split(';')
+        .map(|x| x.parse::<u8>().unwrap_or(0))
+        .collect::<Vec<_>>()
+        .into_iter();
+    fn extended_color(codes: &mut impl Iterator<Item = u8>) -> Color {
+        match codes.next() {
+            Some(5) => Color::AnsiValue(codes.next().unwrap_or(0)),
+            Some(2) => Color::Rgb(
+                codes.next().unwrap_or(0),
+                codes.next().unwrap_or(0),
+                codes.next().unwrap_or(0),
+            ),
+            _ => Color::Default,
+        }
+    }
+    while let Some(code) = codes.next() {
+        match code {
+            0 => attr = Attr::default(),
+            1 => attr.effect |= Effect::BOLD,
+            2 => attr.effect |= Effect::DIM,
+            4 => attr.effect |= Effect::UNDERLINE,
+            5 => attr.effect |= Effect::BLINK,
+            7 => attr.effect |= Effect::REVERSE,
+            22 => attr.effect &= !(Effect::BOLD | Effect::DIM),
+            24 => attr.effect &= !Effect::UNDERLINE,
+            25 => attr.effect &= !Effect::BLINK,
+            27 => attr.effect &= !Effect::REVERSE,
+            30..=37 => attr.fg = Color::AnsiValue(code - 30),
+            38 => attr.fg = extended_color(&mut codes),
+            39 => attr.fg = Color::Default,
+            40..=47 => attr.bg = Color::AnsiValue(code - 40),
+            48 => attr.bg = extended_color(&mut codes),
+            49 => attr.bg = Color::Default,
+            90..=97 => attr.fg = Color::AnsiValue(code - 90 + 8),
+            100..=107 => attr.bg = Color::AnsiValue(code - 100 + 8),
+            _ => {}
+        }
+    }
+    attr
+}
+
+/// The longest prefix of text that fits into width terminal columns.
+/// Control characters count two columns, as they do when tuikit prints them.
+pub fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (pos, c) in text.char_indices() {
+        used += c.width().unwrap_or(2);
+        if used > width {
+            return &text[..pos];
+        }
+    }
+    text
+}
+
+/// The output of a --search-range command, split by day
+pub struct RangeResults {
+    pub range: (chrono::NaiveDate, chrono::NaiveDate),
+    pub by_day: HashMap<chrono::NaiveDate, Vec<String>>,
+}
+
+impl RangeResults {
+    /// Run the range command once for the days visible around dates.date.
+    /// Lines that don't start with 'YYYY-MM-DD<tab>' are ignored.
+    pub fn query(dates: &SearchDates, range_cmd: &str, search_shell: bool) -> Result<RangeResults> {
+        //with --search-shell, the range is in $2 and $3
+        let range_cmd = if search_shell || replace_placeholders(range_cmd, dates).1 {
+            range_cmd.to_string()
+        } else {
+            format!("{} {{range_start}} {{range_end}}", range_cmd)
+        };
+        let output = run_search_command(dates, &range_cmd, search_shell, true)?;
+        let mut by_day: HashMap<chrono::NaiveDate, Vec<String>> = HashMap::new();
+        for line in output.lines() {
+            if let Some((day, text)) = line.split_once('\t') {
+                if let Ok(day) = chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+                    by_day.entry(day).or_default().push(text.to_string());
+                }
+            }
+        }
+        Ok(RangeResults {
+            range: (dates.range_start, dates.range_end),
+            by_day,
+        })
+    }
+
+    pub fn lines(&self, day: chrono::NaiveDate) -> &[String] {
+        self.by_day.get(&day).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// Highlighted days, optionally with a category colour and labels
+#[derive(Default, Clone)]
+pub struct Highlights {
+    pub colors: HashMap<chrono::NaiveDate, Option<Color>>,
+    pub labels: HashMap<chrono::NaiveDate, Vec<String>>,
+}
+
+impl Highlights {
+    pub fn add(&mut self, day: chrono::NaiveDate) {
+        self.colors.entry(day).or_insert(None);
+    }
+
+    pub fn contains(&self, day: &chrono::NaiveDate) -> bool {
+        self.colors.contains_key(day)
+    }
+
+    pub fn color(&self, day: &chrono::NaiveDate) -> Option<Color> {
+        self.colors.get(day).copied().flatten()
+    }
+
+    /// Run a --highlight-command, see Highlights::parse for the output format
+    pub fn query(
+        dates: &SearchDates,
+        highlight_cmd: &str,
+        search_shell: bool,
+    ) -> Result<Highlights> {
+        let output = run_search_command(dates, highlight_cmd, search_shell, false)
+            .context("highlight cmd failed")?;
+        Ok(Highlights::parse(&output))
+    }
+
+    /// Read a --highlight-file, same format as --highlight-command output
+    pub fn from_file(filename: &str) -> Result<Highlights> {
+        let content = std::fs::read_to_string(filename)
+            .with_context(|| format!("Failed to read highlight file '{}'", filename))?;
+        Ok(Highlights::parse(&content))
+    }
+
+    /// Read all --highlight-files. One that doesn't exist (yet), is gone,
+    /// or is halfway written just doesn't highlight anything - it's reloaded once it changes
+    pub fn from_files(filenames: &[String]) -> Highlights {
+        let mut highlights = Highlights::default();
+        for filename in filenames {
+            if let Ok(file_highlights) = Highlights::from_file(filename) {
+                highlights.extend(&file_highlights);
+            }
+        }
+        highlights
+    }
+
+    /// Parse lines of 'YYYY-MM-DD[<tab>label[<tab>category]]',
+    /// unparsable lines are ignored.
+    pub fn parse(input: &str) -> Highlights {
+        let mut result = Highlights::default();
+        for line in input.lines() {
+            let mut parts = line.splitn(3, '\t');
+            let Ok(day) =
+                chrono::NaiveDate::parse_from_str(parts.next().unwrap().trim(), "%Y-%m-%d")
+            else {
+                continue;
+            };
+            result.add(day);
+            if let Some(label) = parts.next().filter(|x| !x.is_empty()) {
+                result
+                    .labels
+                    .entry(day)
+                    .or_default()
+                    .push(label.to_string());
+            }
+            if let Some(category) = parts.next().filter(|x| !x.is_empty()) {
+                //same category, same colour, no matter the order they come in
+                let index = category.bytes().map(|x| x as usize).sum::<usize>();
+                result
+                    .colors
+                    .insert(day, Some(CATEGORY_COLORS[index % CATEGORY_COLORS.len()]));
+            }
+        }
+        result
+    }
+
+    pub fn extend(&mut self, other: &Highlights) {
+        for (day, color) in &other.colors {
+            if color.is_some() || !self.colors.contains_key(day) {
+                self.colors.insert(*day, *color);
+            }
+        }
+        for (day, labels) in &other.labels {
+            self.labels
+                .entry(*day)
+                .or_default()
+                .extend(labels.iter().cloned());
+        }
+    }
+}
+
+/// How weeks are numbered - --week-numbering=...
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WeekNumbering {
+    /// ISO 8601 - weeks start on Monday, week 1 contains the year's first Thursday
+    Iso,
+    /// weeks start on Sunday, week 1 contains January 1st
+    Us,
+    /// January 1st to 7th is week 1, 8th to 14th week 2 and so on
+    Simple,
+    /// broadcast calendar - weeks start on Monday, week 1 contains January 1st
+    Broadcast,
+}
+
+impl WeekNumbering {
+    /// The day calendar rows (and weeks) start with
+    pub fn week_start(self) -> chrono::Weekday {
+        if self == WeekNumbering::Us {
+            chrono::Weekday::Sun
+        } else {
+            chrono::Weekday::Mon
+        }
+    }
+
+    /// First and last day of the week date is in
+    pub fn week_of(self, date: chrono::NaiveDate) -> (chrono::NaiveDate, chrono::NaiveDate) {
+        let days_into_week = (date.weekday().num_days_from_monday() + 7
+            - self.week_start().num_days_from_monday())
+            % 7;
+        let first = date - chrono::Duration::days(days_into_week as i64);
+        (first, first + chrono::Duration::days(6))
+    }
+
+    /// The year the week date is in belongs to, and its number.
+    /// With 'simple', weeks don't line up with calendar rows - those show the number of their first day.
+    pub fn year_and_week(self, date: chrono::NaiveDate) -> (i32, u32) {
+        match self {
+            WeekNumbering::Iso => (date.iso_week().year(), date.iso_week().week()),
+            //the week containing January 1st is week 1 - so a week belongs to the year its last day is in
+            WeekNumbering::Us | WeekNumbering::Broadcast => {
+                let last = self.week_of(date).1;
+                (last.year(), last.ordinal0() / 7 + 1)
+            }
+            WeekNumbering::Simple => (date.year(), date.ordinal0() / 7 + 1),
+        }
+    }
+
+    /// The number of the week date is in
+    pub fn week_number(self, date: chrono::NaiveDate) -> u32 {
+        self.year_and_week(date).1
+    }
+}
+
+/// Fixed length sprints / iterations, counted from --sprint-anchor
+#[derive(Clone, Copy)]
+pub struct Sprints {
+    /// the first day of sprint 1
+    pub anchor: chrono::NaiveDate,
+    /// in days
+    pub length: i64,
+}
+
+impl Sprints {
+    /// The sprint date is in, with its first and last day.
+    /// None if those are beyond the dates chrono can represent.
+    pub fn of(
+        &self,
+        date: chrono::NaiveDate,
+    ) -> Option<(i64, chrono::NaiveDate, chrono::NaiveDate)> {
+        let index = (date - self.anchor).num_days().div_euclid(self.length);
+        let start = self
+            .anchor
+            .checked_add_signed(chrono::Duration::days(index * self.length))?;
+        let end = start.checked_add_signed(chrono::Duration::days(self.length - 1))?;
+        Some((index + 1, start, end))
+    }
+
+    pub fn is_start(&self, date: chrono::NaiveDate) -> bool {
+        self.of(date).is_some_and(|(_, start, _)| start == date)
+    }
+
+    /// 'Sprint 42, day 6/10' - days being working days (Monday to Friday)
+    pub fn describe(&self, date: chrono::NaiveDate) -> String {
+        match self.of(date) {
+            Some((number, start, end)) => format!(
+                "Sprint {}, day {}/{}",
+                number,
+                working_days(start, date),
+                working_days(start, end)
+            ),
+            None => "Sprint ?".to_string(),
+        }
+    }
+}
+
+/// The number of Mondays to Fridays from first to last (inclusive)
+pub fn working_days(first: chrono::NaiveDate, last: chrono::NaiveDate) -> i64 {
+    let days = (last - first).num_days() + 1;
+    let first_weekday = first.weekday().num_days_from_monday() as i64;
+    //whole weeks have five, the days left over start on first's weekday
+    let left_over = (0..days % 7)
+        .filter(|ii| (first_weekday + ii) % 7 < 5)
+        .count() as i64;
+    days / 7 * 5 + left_over
+}
+
+/// A (fiscal) quarter
+pub struct Quarter {
+    /// the year the fiscal year ends in - the calendar year if it starts in January
+    pub fiscal_year: i32,
+    /// 1..=4
+    pub quarter: u32,
+    pub start: chrono::NaiveDate,
+    pub end: chrono::NaiveDate,
+}
+
+impl Quarter {
+    /// The quarter date is in, for a fiscal year starting in month start_month (1..=12)
+    pub fn of(date: chrono::NaiveDate, start_month: u32) -> Quarter {
+        let fiscal_month = (date.month() + 12 - start_month) % 12;
+        let year_start_year = if date.month() >= start_month {
+            date.year()
+        } else {
+            date.year() - 1
+        };
+        let year_start = chrono::NaiveDate::from_ymd_opt(year_start_year, start_month, 1).unwrap();
+        let start = year_start + chrono::Months::new(fiscal_month / 3 * 3);
+        Quarter {
+            fiscal_year: if start_month == 1 {
+                year_start_year
+            } else {
+                year_start_year + 1
+            },
+            quarter: fiscal_month / 3 + 1,
+            start,
+            end: start + chrono::Months::new(3) - chrono::Duration::days(1),
+        }
+    }
+
+    /// FY25-Q2 for fiscal years, 2025-Q2 for calendar ones
+    pub fn name(&self, start_month: u32) -> String {
+        if start_month == 1 {
+            format!("{}-Q{}", self.fiscal_year, self.quarter)
+        } else {
+            format!(
+                "FY{:02}-Q{}",
+                self.fiscal_year.rem_euclid(100),
+                self.quarter
+            )
+        }
+    }
+}
+
+/// How many months are shown, and how many of them side by side
+#[derive(Clone, Copy)]
+pub struct MonthLayout {
+    pub count: usize,
+    pub per_row: usize,
+    /// January to December of the chosen date's year, instead of centered on its month
+    pub whole_year: bool,
+    /// --vertical: the first month shown stays put until the chosen date scrolls out of view
+    pub scrolling: bool,
+    /// where we scrolled to - the first month shown last time
+    pub scrolled_to: Option<chrono::NaiveDate>,
+}
+
+impl MonthLayout {
+    /// As many months per row as fit into width columns, but at least one
+    pub fn new(count: Option<usize>, width: usize) -> MonthLayout {
+        let per_row = ((width + MONTH_GAP) / (MONTH_WIDTH + MONTH_GAP)).max(1);
+        let count = count.unwrap_or(per_row);
+        MonthLayout {
+            count,
+            per_row: per_row.min(count),
+            whole_year: false,
+            scrolling: false,
+            scrolled_to: None,
+        }
+    }
+
+    /// How many rows of months fit into height lines
+    /// (minus the date line and the search results), but at least one
+    fn rows_fitting(height: usize, max_results: usize) -> usize {
+        (height.saturating_sub(1 + max_results) / MONTH_HEIGHT).max(1)
+    }
+
+    /// --vertical - one month per row, as many rows as fit into height lines
+    pub fn vertical(count: Option<usize>, height: usize, max_results: usize) -> MonthLayout {
+        let count = count.unwrap_or(MonthLayout::rows_fitting(height, max_results));
+        MonthLayout {
+            count,
+            per_row: 1,
+            whole_year: false,
+            scrolling: true,
+            scrolled_to: None,
+        }
+    }
+
+    /// --fullscreen - rows of as many months as fit into width columns,
+    /// as many rows as fit into height lines (minus the date line and the search results)
+    pub fn fill(width: usize, height: usize, max_results: usize) -> MonthLayout {
+        let layout = MonthLayout::new(None, width);
+        MonthLayout {
+            count: layout.per_row * MonthLayout::rows_fitting(height, max_results),
+            ..layout
+        }
+    }
+
+    /// The year view - twelve months, four per row if they fit,
+    /// and as many rows as fit into height lines - the others are scrolled to
+    pub fn year(width: usize, height: usize, max_results: usize) -> MonthLayout {
+        let layout = MonthLayout::new(Some(12), width);
+        MonthLayout {
+            per_row: layout.per_row.min(4),
+            whole_year: true,
+            ..layout
+        }
+        .fit(height, max_results)
+    }
+
+    /// No more rows than fit into height lines - fewer months are shown then
+    pub fn fit(self, height: usize, max_results: usize) -> MonthLayout {
+        MonthLayout {
+            count: self
+                .count
+                .min(self.per_row * MonthLayout::rows_fitting(height, max_results)),
+            ..self
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.count.div_ceil(self.per_row)
+    }
+
+    /// The first month shown - the chosen date's month is in the middle,
+    /// or, when scrolling, as close to the previous first month as possible
+    pub fn first_month(&self, date: chrono::NaiveDate) -> chrono::NaiveDate {
+        let month = date.with_day(1).unwrap();
+        if self.whole_year {
+            //rows of the year that don't fit are scrolled to, a row at a time
+            let per_row = self.per_row as u32;
+            let rows = self.rows() as u32;
+            let row = date.month0() / per_row;
+            let first_row = self
+                .scrolled_to
+                .filter(|x| x.year() == date.year())
+                .map(|x| x.month0() / per_row)
+                .unwrap_or(0)
+                .clamp((row + 1).saturating_sub(rows), row)
+                .min(12u32.div_ceil(per_row) - rows);
+            chrono::NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap()
+                + chrono::Months::new(first_row * per_row)
+        } else if let (true, Some(scrolled_to)) = (self.scrolling, self.scrolled_to) {
+            let last_shown = scrolled_to + chrono::Months::new(self.count as u32 - 1);
+            if month < scrolled_to {
+                month
+            } else if month > last_shown {
+                month - chrono::Months::new(self.count as u32 - 1)
+            } else {
+                scrolled_to
+            }
+        } else {
+            date.with_day(1).unwrap() - chrono::Months::new((self.count as u32 - 1) / 2)
+        }
+    }
+
+    /// A terminal high enough for the months, the date line and the search results -
+    /// or the whole (alternate) screen with --fullscreen
+    pub fn term(&self, max_results: usize, fullscreen: bool) -> Term<()> {
+        Term::with_height(if fullscreen {
+            TermHeight::Percent(100)
+        } else {
+            TermHeight::Fixed(self.rows() * MONTH_HEIGHT + 1 + max_results)
+        })
+        .unwrap()
+    }
+
+    /// First and last day of the months shown around date
+    pub fn visible_range(&self, date: chrono::NaiveDate) -> (chrono::NaiveDate, chrono::NaiveDate) {
+        let first_visible = self.first_month(date);
+        let last_visible =
+            first_visible + chrono::Months::new(self.count as u32) - chrono::Duration::days(1);
+        (first_visible, last_visible)
+    }
+}
+
+/// The width and height of the terminal we're running in, before tuikit takes it over
+fn terminal_size() -> (usize, usize) {
+    tuikit::raw::get_tty()
+        .ok()
+        .and_then(|tty| tuikit::output::Output::new(Box::new(tty)).ok())
+        .and_then(|output| output.terminal_size().ok())
+        .unwrap_or((80, 24))
+}
+
+/// Set from --today / FDATE_TODAY
+static TODAY_OVERRIDE: std::sync::OnceLock<chrono::NaiveDate> = std::sync::OnceLock::new();
+
+/// The current date - unless overriden by --today / FDATE_TODAY,
+/// in the --tz timezone if given, the local one otherwise.
+/// Use this instead of chrono::Local::now()
+pub fn today(timezone: Option<chrono_tz::Tz>) -> chrono::NaiveDate {
+    match (TODAY_OVERRIDE.get(), timezone) {
+        (Some(today), _) => *today,
+        (None, Some(tz)) => chrono::Utc::now().with_timezone(&tz).date_naive(),
+        (None, None) => chrono::Local::now().naive_local().date(),
+    }
+}
+
+/// What the user picks - --pick=...
+#[derive(Clone, Copy, PartialEq)]
+pub enum Pick {
+    Day,
+    Week,
+    Month,
+    Quarter,
+    Year,
+}
+
+/// How --datetime results are printed
+#[derive(Clone, Copy, PartialEq)]
+pub enum DatetimeOutput {
+    /// YYYY-MM-DDTHH:MM[:SS], no timezone
+    Naive,
+    /// RFC 3339 with the offset of the chosen timezone
+    Rfc3339,
+    /// RFC 3339, converted to UTC
+    Utc,
+    /// seconds since 1970-01-01 UTC
+    Epoch,
+}
+
+/// Attach a timezone (None = the local one) to a date and time.
+/// Ambiguous times (DST ending) resolve to the earlier one, non-existing ones (DST starting) to None
+pub fn resolve_datetime(
+    naive: chrono::NaiveDateTime,
+    zone: Option<chrono_tz::Tz>,
+) -> chrono::LocalResult<chrono::DateTime<chrono::FixedOffset>> {
+    use chrono::TimeZone;
+    match zone {
+        Some(tz) => tz.from_local_datetime(&naive).map(|x| x.fixed_offset()),
+        None => chrono::Local
+            .from_local_datetime(&naive)
+            .map(|x| x.fixed_offset()),
+    }
+}
+
+/// The current time of day, in the --tz timezone if given
+pub fn now(timezone: Option<chrono_tz::Tz>) -> chrono::NaiveTime {
+    match timezone {
+        Some(tz) => chrono::Utc::now().with_timezone(&tz).time(),
+        None => chrono::Local::now().time(),
     }
-    parsed
 }
 
 pub fn print_help() {
@@ -50,17 +823,74 @@ pub fn print_help() {
     println!("\t digits - enter date. No '-' necessary.");
     println!("\t tab - jump two next section of date (so year/month/day)");
     println!("\t backspace - go back one character in entered date");
-    println!("\t Enter - leave, print chosen date, exit code 0");
+    println!("\t n/N - with --search or --search-range: go to the next/previous date for which the (shown) searches return something");
+    println!("\t With --pick=month, --pick=quarter or --pick=year:");
+    println!("\t\t left/right: one month/quarter/year back/forward");
+    println!("\t\t up/down: one row of the grid (one year for quarters) back/forward");
+    println!(
+        "\t\t page up/page down: one year (month, quarter) or {YEAR_GRID_YEARS} years (year) back/forward"
+    );
+    println!("\t [/] - with --sprint-anchor: go to the first/last day of the sprint (or the previous/next one)");
+    println!("\t y - toggle the year view (all twelve months of the chosen date's year)");
+    println!("\t r - refresh: rerun searches and highlight commands, reload highlight files");
+    println!("\t p - with multiple --search: cycle between showing all / a single search pane");
+    println!(
+        "\t Enter - leave, print chosen date, exit code 0 (with --datetime: go on to the time)"
+    );
+    println!("\t With --datetime, when editing the time:");
+    println!("\t\t up/down: one --time-step later/earlier (past midnight, the date moves along)");
+    println!("\t\t digits - enter time. No ':' necessary.");
+    println!("\t\t tab - jump to next section of the time (hour/minute/second)");
+    println!("\t\t backspace - go back one character, from the first back to the date");
+    println!("\t\t z - cycle through the --tz-choices timezones");
+    println!("\t\t Enter - leave, print chosen date and time (see --datetime-output), exit code 0");
+    println!("\t\t the other date keys do nothing - backspace back to the date first");
     println!("\t Escape - leave, exit code 1");
-    println!("");
+    println!();
     println!("CLI options");
     println!("\t -h | --help - print this help");
     println!("\t YYYY--mm-dd - default / start date (ISO format)");
+    println!("\t --pick=day|week|month|year - what to pick. Week highlights whole calendar rows, output is YYYY-Www (ISO week, or per --week-numbering). Month and year show a grid of months/years, output is YYYY-MM / YYYY. Default: day");
+    println!("\t --pick=quarter - pick a quarter of the (fiscal) year. Output is 'FY25-Q2 2024-07-01 2024-09-30' (name, first and last day), or '2025-Q2 ...' for calendar years");
+    println!("\t --fiscal-year-start=<month> - for --pick=quarter: the month (1-12) the fiscal year starts in. Fiscal years are named after the year they end in. Default: 1");
+    println!("\t --sprint-anchor=YYYY-MM-DD - the first day of sprint 1. Marks the first day of each sprint with a '|', shows 'Sprint 42, day 6/10' (working days) after the date");
+    println!("\t --sprint-length=<days> - for --sprint-anchor: days per sprint, at most {SPRINT_LENGTH_MAX}. Default: {SPRINT_LENGTH_DEFAULT}");
+    println!("\t --week-numbering=iso|us|simple|broadcast - week numbers in the calendar and after 'WN': ISO 8601 (default), Sunday based weeks with week 1 containing January 1st (us), January 1st-7th is week 1 (simple), or Monday based weeks with week 1 containing January 1st (broadcast). With us, calendar rows start on Sunday. Simple weeks don't line up with calendar rows, which show the number of their first day - and can't be used with --pick=week");
+    println!("\t --week-output=week|range - for --pick=week: output YYYY-Www, or the first and last day (Monday and Sunday for ISO weeks) as 'YYYY-MM-DD YYYY-MM-DD'. Default: week");
+    println!("\t --months=<number>|auto - how many months to show, wrapped into rows if the terminal is too narrow. 'auto' shows as many as fit next to each other. Default: {MONTHS_DEFAULT}");
+    println!("\t --fullscreen - use the whole terminal (alternate screen). Without --months, shows as many months as fit, and the search output gets the rest of the screen");
+    println!("\t --vertical - stack the months top to bottom, for narrow panes. The view scrolls when the chosen date leaves it. With --months=auto: as many months as fit the terminal's height");
+    println!("\t --year-view - start in the year view (all twelve months, toggle with 'y')");
+    println!("\t --today=<iso-date> - pretend today is this date (for past/future colouring, './</>' and the default date). Can also be set with the FDATE_TODAY environment variable");
+    println!("\t --tz=<IANA timezone> - compute today/tomorrow/yesterday in this timezone (e.g. Europe/Berlin) instead of the local one");
     println!("\t --german - enter dates in German style (dd.mm.yyyy) in the TUI; output remains YYYY-MM-DD");
     println!("\t --title=<whatever> - show this as title (before chosen date)");
     println!("\t --highlight=<iso-date> - Highlight this date (can be passed multiple times)");
+    println!("\t --highlight-command=<external command> - run at startup and whenever the visible months change, highlights the dates it prints. Output lines: 'YYYY-MM-DD[<tab>label[<tab>category]]'. Labels are shown next to the chosen date, categories get their own colour. Placeholders as for --search, but the date is never appended");
+    println!("\t --highlight-file=<filename> - highlight the dates in this file, same format as --highlight-command output. Reloaded when it changes, need not exist yet (can be passed multiple times)");
+    println!("\t --watch=<filename> - rerun searches and highlight commands whenever this file changes (can be passed multiple times)");
+    println!("\t --refresh-interval=<seconds> - rerun searches and highlight commands this often");
     println!("\t --search=<external command> - Whenever the date is changed, call this command with the date as argument. Use '{{}}' as placeholder for the date. The results are shown below the date selection, up to --max-results lines");
+    println!("\t\t Can be passed multiple times. Each search gets its own pane, 'p' cycles between showing all or just one of them");
+    println!("\t --search-named=<name>:<external command> - a --search with a name, shown above its pane (--search-named=git:'git log --since {{}}'). Everything up to the first ':' is the name");
+    println!("\t\t Further placeholders: {{%<strftime format>}} (e.g. {{%d.%m.%Y}}), {{year}}, {{month}}, {{day}}, {{week}}, {{weekday}}, {{range_start}}, {{range_end}} (first/last visible date)");
+    println!("\t\t The command also sees the environment variables FDATE_DATE, FDATE_WEEKDAY, FDATE_ISOWEEK, FDATE_ISOYEAR, FDATE_DEFAULT_DATE and FDATE_TODAY");
+    println!("\t --search-range=<external command> - call this command once for all visible days, with the first and last visible date as arguments ($2 and $3 with --search-shell, or use '{{range_start}}' and '{{range_end}}' placeholders). Output lines are expected as 'YYYY-MM-DD<tab>text'. Days with output are highlighted, the chosen day's lines shown in their own pane (and used for --heatmap if there is no other search)");
+    println!("\t --search-range-named=<name>:<external command> - a --search-range with a name, as for --search-named");
+    println!("\t --strip-ansi - remove ANSI colour codes from the --search output instead of showing the colours");
+    println!("\t --search-shell - run --search (and --count-command) via 'sh -c', so pipes and redirections work. Instead of being appended, the date is passed as $1 (and the first/last visible day as $2/$3)");
     println!("\t --max-results=<number> - Maximum number of lines to show for --search. Default: {MAX_SEARCH_RESULT_DEFAULT}");
+    println!("\t --jump-limit=<days> - how many days n/N search before giving up. Default: {JUMP_LIMIT_DEFAULT}");
+    println!("\t --heatmap - run the --search command for every visible day and shade the days by the number of output lines");
+    println!("\t --count-command=<external command> - like --heatmap, but use this command instead. If it prints a number, that's the count, otherwise the number of lines is used. Implies --heatmap");
+    println!("\t --datetime - after the date, pick a time of day. Output is an ISO 8601 datetime");
+    println!("\t --time=<HH:MM[:SS]> - default time for --datetime. Default: now, rounded down to --time-step");
+    println!(
+        "\t --time-step=<minutes> - how far up/down move the time, at least 1. Default: {TIME_STEP_DEFAULT}"
+    );
+    println!("\t --datetime-output=naive|rfc3339|utc|epoch - --datetime output: without timezone (default), RFC 3339 with offset, RFC 3339 in UTC, or seconds since the epoch. The time is taken to be in the --tz timezone, or the local one");
+    println!("\t --tz-choices=<IANA timezone>,... - further timezones to cycle through with 'z' when editing the time");
+    println!("\t --seconds - include seconds in the --datetime time");
     println!("\t --output-filename<filename> - write chosen date to this file as well as outputing it on stdout");
 }
 
@@ -84,18 +914,168 @@ fn is_string_iso_date(maybe_a_date: &str) -> bool {
 
 fn main() -> Result<()> {
     let mut title = "".to_string();
-    let mut highlights = Vec::new();
-    let mut search = None;
+    let mut highlights = Highlights::default();
+    let mut highlight_command = None;
+    let mut highlight_files = Vec::new();
+    let mut watch_files = Vec::new();
+    let mut refresh_interval = None;
+    let mut searches = Vec::new();
+    let mut range_search = None;
     let mut max_results = MAX_SEARCH_RESULT_DEFAULT;
+    let mut jump_limit = JUMP_LIMIT_DEFAULT;
     let mut sort_search = false;
     let mut output_filename = None;
     let mut debug = false;
     let mut german = false;
-    let mut start_date: chrono::NaiveDate = chrono::Local::now().naive_local().date();
+    let mut heatmap = false;
+    let mut count_command = None;
+    let mut search_shell = false;
+    let mut strip_ansi = false;
+    let mut start_date = None;
+    let mut months = Some(MONTHS_DEFAULT);
+    let mut vertical = false;
+    let mut months_given = false;
+    let mut fullscreen = false;
+    let mut pick = Pick::Day;
+    let mut week_output_range = false;
+    let mut fiscal_year_start = 1;
+    let mut sprint_anchor = None;
+    let mut sprint_length = SPRINT_LENGTH_DEFAULT;
+    let mut year_view = false;
+    let mut datetime = false;
+    let mut start_time = None;
+    let mut time_step = TIME_STEP_DEFAULT;
+    let mut seconds = false;
+    let mut datetime_output = DatetimeOutput::Naive;
+    let mut tz_choices = Vec::new();
+    let mut today_override = std::env::var("FDATE_TODAY").ok();
+    let mut timezone: Option<chrono_tz::Tz> = None;
+    let mut week_numbering = WeekNumbering::Iso;
     for arg in std::env::args().skip(1) {
         if arg == "--help" || arg == "-h" {
             print_help();
             std::process::exit(0);
+        } else if arg.starts_with("--months=") {
+            months_given = true;
+            months = match arg.strip_prefix("--months=").unwrap() {
+                "auto" => None,
+                x => Some(
+                    x.parse::<usize>()
+                        .ok()
+                        .filter(|x| *x > 0)
+                        .with_context(|| format!("Failed to parse months '{}'", arg))?,
+                ),
+            };
+        } else if arg.starts_with("--pick=") {
+            pick = match arg.strip_prefix("--pick=").unwrap() {
+                "day" => Pick::Day,
+                "week" => Pick::Week,
+                "month" => Pick::Month,
+                "quarter" => Pick::Quarter,
+                "year" => Pick::Year,
+                _ => {
+                    println!("Unknown --pick '{}'", arg);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg.starts_with("--fiscal-year-start=") {
+            fiscal_year_start = arg
+                .strip_prefix("--fiscal-year-start=")
+                .unwrap()
+                .parse::<u32>()
+                .ok()
+                .filter(|x| (1..=12).contains(x))
+                .with_context(|| format!("Failed to parse fiscal_year_start '{}'", arg))?;
+        } else if arg.starts_with("--week-output=") {
+            week_output_range = match arg.strip_prefix("--week-output=").unwrap() {
+                "week" => false,
+                "range" => true,
+                _ => {
+                    println!("Unknown --week-output '{}'", arg);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg == "--fullscreen" {
+            fullscreen = true;
+        } else if arg == "--vertical" {
+            vertical = true;
+        } else if arg == "--year-view" {
+            year_view = true;
+        } else if arg.starts_with("--today=") {
+            today_override = Some(arg.strip_prefix("--today=").unwrap().to_string());
+        } else if arg.starts_with("--tz=") {
+            let tz = arg.strip_prefix("--tz=").unwrap();
+            timezone = Some(
+                tz.parse::<chrono_tz::Tz>()
+                    .map_err(|e| anyhow::anyhow!("Unknown timezone '{}': {}", tz, e))?,
+            );
+        } else if arg == "--datetime" {
+            datetime = true;
+        } else if arg.starts_with("--time=") {
+            let time = arg.strip_prefix("--time=").unwrap();
+            start_time = Some(
+                chrono::NaiveTime::parse_from_str(time, "%H:%M:%S")
+                    .or_else(|_| chrono::NaiveTime::parse_from_str(time, "%H:%M"))
+                    .with_context(|| format!("Failed to parse time '{}'", time))?,
+            );
+        } else if arg.starts_with("--time-step=") {
+            time_step = arg
+                .strip_prefix("--time-step=")
+                .unwrap()
+                .parse::<u32>()
+                .ok()
+                .filter(|x| *x > 0)
+                .with_context(|| format!("Failed to parse time_step '{}' (minutes, > 0)", arg))?;
+        } else if arg.starts_with("--sprint-anchor=") {
+            let anchor = arg.strip_prefix("--sprint-anchor=").unwrap();
+            sprint_anchor = Some(
+                chrono::NaiveDate::parse_from_str(anchor, "%Y-%m-%d")
+                    .with_context(|| format!("Failed to parse sprint anchor '{}'", anchor))?,
+            );
+        } else if arg.starts_with("--sprint-length=") {
+            sprint_length = arg
+                .strip_prefix("--sprint-length=")
+                .unwrap()
+                .parse::<i64>()
+                .ok()
+                .filter(|x| (1..=SPRINT_LENGTH_MAX).contains(x))
+                .with_context(|| {
+                    format!(
+                        "Failed to parse sprint_length '{}' (1 to {} days)",
+                        arg, SPRINT_LENGTH_MAX
+                    )
+                })?;
+        } else if arg.starts_with("--week-numbering=") {
+            week_numbering = match arg.strip_prefix("--week-numbering=").unwrap() {
+                "iso" => WeekNumbering::Iso,
+                "us" => WeekNumbering::Us,
+                "simple" => WeekNumbering::Simple,
+                "broadcast" => WeekNumbering::Broadcast,
+                _ => {
+                    println!("Unknown --week-numbering '{}'", arg);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg.starts_with("--datetime-output=") {
+            datetime_output = match arg.strip_prefix("--datetime-output=").unwrap() {
+                "naive" => DatetimeOutput::Naive,
+                "rfc3339" => DatetimeOutput::Rfc3339,
+                "utc" => DatetimeOutput::Utc,
+                "epoch" => DatetimeOutput::Epoch,
+                _ => {
+                    println!("Unknown --datetime-output '{}'", arg);
+                    std::process::exit(1);
+                }
+            };
+        } else if arg.starts_with("--tz-choices=") {
+            for tz in arg.strip_prefix("--tz-choices=").unwrap().split(',') {
+                tz_choices.push(
+                    tz.parse::<chrono_tz::Tz>()
+                        .map_err(|e| anyhow::anyhow!("Unknown timezone '{}': {}", tz, e))?,
+                );
+            }
+        } else if arg == "--seconds" {
+            seconds = true;
         } else if arg == "--german" {
             german = true;
         } else if arg.starts_with("--title=") {
@@ -103,13 +1083,53 @@ fn main() -> Result<()> {
             title.push_str(": ");
         } else if arg.starts_with("--highlight=") {
             for adate in arg.strip_prefix("--highlight=").unwrap().split(',') {
-                highlights.push(
+                highlights.add(
                     chrono::NaiveDate::parse_from_str(adate, "%Y-%m-%d")
                         .with_context(|| format!("Failed to parsed date '{}'", adate))?,
                 );
             }
+        } else if arg.starts_with("--highlight-file=") {
+            highlight_files.push(arg.strip_prefix("--highlight-file=").unwrap().to_string());
+        } else if arg.starts_with("--watch=") {
+            watch_files.push(arg.strip_prefix("--watch=").unwrap().to_string());
+        } else if arg.starts_with("--refresh-interval=") {
+            refresh_interval = Some(std::time::Duration::from_secs(
+                arg.strip_prefix("--refresh-interval=")
+                    .unwrap()
+                    .parse::<u64>()
+                    .with_context(|| format!("Failed to parse refresh_interval '{}'", arg))?,
+            ));
+        } else if arg.starts_with("--highlight-command=") {
+            highlight_command = Some(
+                arg.strip_prefix("--highlight-command=")
+                    .unwrap()
+                    .to_string(),
+            );
         } else if arg.starts_with("--search=") {
-            search = Some(arg.strip_prefix("--search=").unwrap().to_string());
+            searches.push(Search::new(arg.strip_prefix("--search=").unwrap()));
+        } else if arg.starts_with("--search-named=") {
+            searches.push(
+                Search::named(arg.strip_prefix("--search-named=").unwrap()).with_context(|| {
+                    format!("Expected --search-named=name:command, got '{}'", arg)
+                })?,
+            );
+        } else if arg.starts_with("--search-range=") {
+            range_search = Some(Search::new(arg.strip_prefix("--search-range=").unwrap()));
+        } else if arg.starts_with("--search-range-named=") {
+            range_search = Some(
+                Search::named(arg.strip_prefix("--search-range-named=").unwrap()).with_context(
+                    || format!("Expected --search-range-named=name:command, got '{}'", arg),
+                )?,
+            );
+        } else if arg == "--search-shell" {
+            search_shell = true;
+        } else if arg == "--strip-ansi" {
+            strip_ansi = true;
+        } else if arg == "--heatmap" {
+            heatmap = true;
+        } else if arg.starts_with("--count-command=") {
+            count_command = Some(arg.strip_prefix("--count-command=").unwrap().to_string());
+            heatmap = true;
         } else if arg.starts_with("--sort-search") {
             sort_search = true;
         } else if arg.starts_with("--output-filename=") {
@@ -120,33 +1140,306 @@ fn main() -> Result<()> {
                 .unwrap()
                 .parse::<usize>()
                 .with_context(|| format!("Failed to parse max_results '{}'", arg))?;
+        } else if arg.starts_with("--jump-limit=") {
+            jump_limit = arg
+                .strip_prefix("--jump-limit=")
+                .unwrap()
+                .parse::<usize>()
+                .with_context(|| format!("Failed to parse jump_limit '{}'", arg))?;
         } else if arg == "--debug" {
             debug = true;
         } else if is_string_iso_date(&arg) {
-            start_date = chrono::NaiveDate::parse_from_str(&arg, "%Y-%m-%d")
-                .with_context(|| format!("Failed to parsed date '{}'", arg))?;
+            start_date = Some(
+                chrono::NaiveDate::parse_from_str(&arg, "%Y-%m-%d")
+                    .with_context(|| format!("Failed to parsed date '{}'", arg))?,
+            );
         } else {
             println!("Unknown argument '{}'", arg);
             std::process::exit(1);
         }
     }
-    let term: Term<()> = Term::with_height(TermHeight::Fixed(10 + max_results)).unwrap();
+    if let Some(today_override) = today_override {
+        TODAY_OVERRIDE
+            .set(
+                chrono::NaiveDate::parse_from_str(&today_override, "%Y-%m-%d")
+                    .with_context(|| format!("Failed to parse today '{}'", today_override))?,
+            )
+            .unwrap();
+    }
+    //without an explicit start date, the default date follows 'today' over midnight
+    let explicit_start_date = start_date.is_some();
+    let mut start_date = start_date.unwrap_or_else(|| today(timezone));
+    let sprints = sprint_anchor.map(|anchor| Sprints {
+        anchor,
+        length: sprint_length,
+    });
+    if pick == Pick::Week && week_numbering == WeekNumbering::Simple {
+        println!("--pick=week does not work with --week-numbering=simple - its weeks start on whatever day January 1st is");
+        std::process::exit(1);
+    }
+    if datetime && pick != Pick::Day {
+        println!("--datetime only works with --pick=day");
+        std::process::exit(1);
+    }
+    //months and years are always entered as YYYY-MM(-DD)
+    let german = german && (pick == Pick::Day || pick == Pick::Week);
+    let time_format = if seconds { "%H:%M:%S" } else { "%H:%M" };
+    let mut time = start_time.unwrap_or_else(|| {
+        let now = now(timezone);
+        let minutes = now.hour() * 60 + now.minute();
+        let minutes = minutes - minutes % time_step;
+        chrono::NaiveTime::from_hms_opt(minutes / 60, minutes % 60, 0).unwrap()
+    });
+    let mut editing_time = false;
+    //None is the local timezone
+    let mut zones = vec![timezone];
+    for tz in tz_choices {
+        if !zones.contains(&Some(tz)) {
+            zones.push(Some(tz));
+        }
+    }
+    let mut zone_index = 0;
+    let mut time_column: usize = 0;
+    let heat_cmd = if heatmap {
+        match (&count_command, searches.first()) {
+            (Some(count_command), _) => Some((count_command.clone(), true)),
+            (None, Some(search)) => Some((search.cmd.clone(), false)),
+            (None, None) if range_search.is_some() => None,
+            (None, None) => {
+                println!("--heatmap requires either --search, --search-range or --count-command");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+    //recalculated whenever the terminal is resized.
+    //--months=N that wrap into more rows than fit show fewer months
+    let month_layout_for = |(width, height)| {
+        if vertical {
+            MonthLayout::vertical(months, height, max_results)
+        } else if fullscreen && !months_given {
+            MonthLayout::fill(width, height, max_results)
+        } else {
+            MonthLayout::new(months, width)
+        }
+        .fit(height, max_results)
+    };
+    let (terminal_width, terminal_height) = terminal_size();
+    let mut month_layout = month_layout_for((terminal_width, terminal_height));
+    let mut layout = if year_view {
+        MonthLayout::year(terminal_width, terminal_height, max_results)
+    } else {
+        month_layout
+    };
+    let mut term = layout.term(max_results, fullscreen);
     let mut cursor_column: usize = 0;
     let mut date = start_date;
+    let mut heat_counts: HashMap<chrono::NaiveDate, usize> = HashMap::new();
+    //None: show all search panes side by side, Some(x): only the xth
+    let mut shown_pane: Option<usize> = None;
+    let mut search_cache: HashMap<(String, chrono::NaiveDate), String> = HashMap::new();
+    let mut range_results: Option<RangeResults> = None;
+    let mut command_highlights: Option<((chrono::NaiveDate, chrono::NaiveDate), Highlights)> = None;
+    let pane_count = searches.len() + range_search.iter().count();
+    let mut file_highlights = Highlights::from_files(&highlight_files);
+    watch_files.extend(highlight_files.iter().cloned());
+    let mut watch_times = modification_times(&watch_files);
+    let mut last_refresh = std::time::Instant::now();
+    let mut last_today = today(timezone);
 
-    while let Ok(ev) = term.poll_event() {
+    loop {
+        let ev = match term.peek_event(WATCH_INTERVAL) {
+            Ok(ev) => ev,
+            Err(tuikit::error::TuikitError::Timeout(_)) => {
+                let new_watch_times = modification_times(&watch_files);
+                let today = today(timezone);
+                let refresh_due = refresh_interval.is_some_and(|x| last_refresh.elapsed() >= x);
+                if new_watch_times == watch_times && today == last_today && !refresh_due {
+                    continue;
+                }
+                watch_times = new_watch_times;
+                if !explicit_start_date {
+                    start_date = today;
+                }
+                last_today = today;
+                //Event::User is our 'redraw with fresh data'
+                Event::User(())
+            }
+            Err(_) => break,
+        };
         let _ = term.clear();
+        if ev == Event::User(()) || ev == Event::Key(Key::Char('r')) {
+            file_highlights = Highlights::from_files(&highlight_files);
+            search_cache.clear();
+            heat_counts.clear();
+            last_refresh = std::time::Instant::now();
+            range_results = None;
+            command_highlights = None;
+        }
 
-        let (width, _height) = term.term_size().unwrap();
+        let (width, height) = term.term_size().unwrap();
+        let mut message = None;
         match ev {
+            Event::Key(Key::Enter) if datetime && !editing_time => {
+                editing_time = true;
+            }
+            Event::Key(Key::Enter)
+                if datetime
+                    && datetime_output != DatetimeOutput::Naive
+                    && resolve_datetime(date.and_time(time), zones[zone_index])
+                        .earliest()
+                        .is_none() =>
+            {
+                message = Some("this time does not exist in this timezone (DST)".to_string());
+            }
             Event::Key(Key::Enter) => {
                 term.clear()?;
-                println!("{}", date.format("%Y-%m-%d"));
+                let output = if datetime {
+                    let resolved = resolve_datetime(date.and_time(time), zones[zone_index]);
+                    let resolved = resolved.earliest();
+                    match datetime_output {
+                        DatetimeOutput::Naive => {
+                            format!("{}T{}", date.format("%Y-%m-%d"), time.format(time_format))
+                        }
+                        DatetimeOutput::Rfc3339 => resolved
+                            .unwrap()
+                            .to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+                        DatetimeOutput::Utc => resolved
+                            .unwrap()
+                            .with_timezone(&chrono::Utc)
+                            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                        DatetimeOutput::Epoch => resolved.unwrap().timestamp().to_string(),
+                    }
+                } else {
+                    match pick {
+                        Pick::Day => date.format("%Y-%m-%d").to_string(),
+                        Pick::Week if week_output_range => {
+                            let (monday, sunday) = week_numbering.week_of(date);
+                            format!(
+                                "{} {}",
+                                monday.format("%Y-%m-%d"),
+                                sunday.format("%Y-%m-%d")
+                            )
+                        }
+                        Pick::Week => {
+                            let (year, week) = week_numbering.year_and_week(date);
+                            format!("{}-W{:02}", year, week)
+                        }
+                        Pick::Month => date.format("%Y-%m").to_string(),
+                        Pick::Quarter => {
+                            let quarter = Quarter::of(date, fiscal_year_start);
+                            format!(
+                                "{} {} {}",
+                                quarter.name(fiscal_year_start),
+                                quarter.start.format("%Y-%m-%d"),
+                                quarter.end.format("%Y-%m-%d")
+                            )
+                        }
+                        Pick::Year => date.format("%Y").to_string(),
+                    }
+                };
+                println!("{}", output);
                 if let Some(output_filename) = &output_filename {
-                    std::fs::write(output_filename, date.format("%Y-%m-%d").to_string())?;
+                    std::fs::write(output_filename, output)?;
                 }
                 std::process::exit(0);
             }
+            Event::Key(Key::Up) | Event::Key(Key::Down) if editing_time => {
+                let step = chrono::Duration::minutes(time_step as i64);
+                let step = if ev == Event::Key(Key::Up) {
+                    step
+                } else {
+                    -step
+                };
+                //stepping past midnight moves the date along
+                let (new_time, carry_seconds) = time.overflowing_add_signed(step);
+                time = new_time;
+                date += chrono::Duration::seconds(carry_seconds);
+            }
+            Event::Key(Key::Backspace) if editing_time => {
+                if time_column == 0 {
+                    editing_time = false;
+                } else {
+                    time_column -= 1;
+                    if time_column == 2 || time_column == 5 {
+                        time_column -= 1;
+                    }
+                }
+            }
+            Event::Key(Key::Tab) if editing_time => {
+                time_column = if time_column < 3 {
+                    3
+                } else if time_column < 6 && seconds {
+                    6
+                } else {
+                    0
+                };
+            }
+            Event::Key(Key::Char('z')) if editing_time => {
+                zone_index = (zone_index + 1) % zones.len();
+            }
+            Event::Key(Key::Char(c)) if editing_time && c.is_ascii_digit() => {
+                add_time_digit(
+                    &mut time_column,
+                    c.to_digit(10).unwrap() as u8,
+                    &mut time,
+                    time_format,
+                );
+            }
+            //the date stays put while editing the time - backspace gets you back to it
+            Event::Key(Key::Char(c)) if editing_time && !matches!(c, 'q' | 'r' | 'p' | 'y') => {}
+            Event::Key(
+                Key::Left | Key::Right | Key::PageUp | Key::PageDown | Key::Home | Key::End,
+            ) if editing_time => {}
+            Event::Key(Key::Up) if pick == Pick::Month => {
+                date = date - chrono::Months::new(4);
+            }
+            Event::Key(Key::Down) if pick == Pick::Month => {
+                date = date + chrono::Months::new(4);
+            }
+            Event::Key(Key::Left) if pick == Pick::Month => {
+                date = date - chrono::Months::new(1);
+            }
+            Event::Key(Key::Right) if pick == Pick::Month => {
+                date = date + chrono::Months::new(1);
+            }
+            Event::Key(Key::PageUp) if pick == Pick::Month => {
+                date = date - chrono::Months::new(12);
+            }
+            Event::Key(Key::PageDown) if pick == Pick::Month => {
+                date = date + chrono::Months::new(12);
+            }
+            Event::Key(Key::Left) if pick == Pick::Quarter => {
+                date = date - chrono::Months::new(3);
+            }
+            Event::Key(Key::Right) if pick == Pick::Quarter => {
+                date = date + chrono::Months::new(3);
+            }
+            Event::Key(Key::Up) | Event::Key(Key::PageUp) if pick == Pick::Quarter => {
+                date = date - chrono::Months::new(12);
+            }
+            Event::Key(Key::Down) | Event::Key(Key::PageDown) if pick == Pick::Quarter => {
+                date = date + chrono::Months::new(12);
+            }
+            Event::Key(Key::Up) if pick == Pick::Year => {
+                date = date - chrono::Months::new(4 * 12);
+            }
+            Event::Key(Key::Down) if pick == Pick::Year => {
+                date = date + chrono::Months::new(4 * 12);
+            }
+            Event::Key(Key::Left) if pick == Pick::Year => {
+                date = date - chrono::Months::new(12);
+            }
+            Event::Key(Key::Right) if pick == Pick::Year => {
+                date = date + chrono::Months::new(12);
+            }
+            Event::Key(Key::PageUp) if pick == Pick::Year => {
+                date = date - chrono::Months::new(YEAR_GRID_YEARS as u32 * 12);
+            }
+            Event::Key(Key::PageDown) if pick == Pick::Year => {
+                date = date + chrono::Months::new(YEAR_GRID_YEARS as u32 * 12);
+            }
             Event::Key(Key::Up) => {
                 date -= chrono::Duration::days(7);
             }
@@ -204,17 +1497,102 @@ fn main() -> Result<()> {
                 }
             }
             Event::Key(Key::Char('.')) => {
-                date = chrono::Local::now().naive_local().date();
+                date = today(timezone);
+            }
+            Event::Key(Key::Char('n')) | Event::Key(Key::Char('N')) if pane_count == 0 => {
+                message = Some("n/N need a --search or --search-range".to_string());
+            }
+            Event::Key(Key::Char('n')) | Event::Key(Key::Char('N')) => {
+                let step = if ev == Event::Key(Key::Char('n')) {
+                    1
+                } else {
+                    -1
+                };
+                match find_next_result(
+                    &term,
+                    height.saturating_sub(1),
+                    date,
+                    step,
+                    jump_limit,
+                    start_date,
+                    timezone,
+                    &layout,
+                    &shown_searches(&searches, shown_pane),
+                    //the range pane comes after the --search ones
+                    range_search
+                        .as_ref()
+                        .filter(|_| shown_pane.is_none() || shown_pane == Some(searches.len())),
+                    search_shell,
+                    &mut search_cache,
+                )? {
+                    Some(found) => date = found,
+                    None => message = Some(format!("no results within {} days", jump_limit)),
+                }
+            }
+            Event::Key(Key::Char('y')) => {
+                year_view = !year_view;
+                layout = if year_view {
+                    let (terminal_width, terminal_height) = terminal_size();
+                    MonthLayout::year(terminal_width, terminal_height, max_results)
+                } else {
+                    month_layout
+                };
+                //the height is fixed once the term is started, so we need a new one.
+                //Which will send a Restarted event, and we'll redraw then.
+                if !fullscreen {
+                    drop(term);
+                    term = layout.term(max_results, fullscreen);
+                    continue;
+                }
+            }
+            Event::Resize { .. } => {
+                //tuikit reports the size of our term, not that of the terminal
+                let (terminal_width, terminal_height) = terminal_size();
+                month_layout = month_layout_for((terminal_width, terminal_height));
+                let rows = layout.rows();
+                layout = MonthLayout {
+                    scrolled_to: layout.scrolled_to,
+                    ..if year_view {
+                        MonthLayout::year(terminal_width, terminal_height, max_results)
+                    } else {
+                        month_layout
+                    }
+                };
+                if !fullscreen && layout.rows() != rows {
+                    drop(term);
+                    term = layout.term(max_results, fullscreen);
+                    continue;
+                }
+            }
+            Event::Key(Key::Char('[')) if sprints.is_some() => {
+                //from a sprint's first day, go on to the previous one's
+                let sprints = sprints.unwrap();
+                if let Some((_, start, _)) = sprints.of(date - chrono::Duration::days(1)) {
+                    date = start;
+                }
+            }
+            Event::Key(Key::Char(']')) if sprints.is_some() => {
+                let sprints = sprints.unwrap();
+                if let Some((_, _, end)) = sprints.of(date + chrono::Duration::days(1)) {
+                    date = end;
+                }
+            }
+            Event::Key(Key::Char('p')) if pane_count > 1 => {
+                shown_pane = match shown_pane {
+                    None => Some(0),
+                    Some(x) if x + 1 < pane_count => Some(x + 1),
+                    Some(_) => None,
+                };
             }
             Event::Key(Key::Char(',')) => {
                 date = start_date;
             }
 
             Event::Key(Key::Char('<')) => {
-                date = chrono::Local::now().naive_local().date() + chrono::Duration::days(-1);
+                date = today(timezone) + chrono::Duration::days(-1);
             }
             Event::Key(Key::Char('>')) => {
-                date = chrono::Local::now().naive_local().date() + chrono::Duration::days(1);
+                date = today(timezone) + chrono::Duration::days(1);
             }
             Event::Key(Key::Char('m')) => {
                 //advance to next tuesday
@@ -353,84 +1731,320 @@ fn main() -> Result<()> {
             }
             _ => {}
         }
+        //the day (and month) part of the date isn't shown when picking months / years
+        match pick {
+            Pick::Month | Pick::Quarter if cursor_column >= 8 => cursor_column = 0,
+            Pick::Year if cursor_column >= 5 => cursor_column = 0,
+            _ => {}
+        }
 
-        let mut cal_col = 0;
-        let cal_row = 0;
-        let (_used_row, used_col) = write_cal(
-            date - chrono::Months::new(1),
-            date,
-            &term,
-            cal_col,
-            cal_row,
-            &highlights,
-        );
-        cal_col = used_col + 2;
+        if let Some(range_search) = &range_search {
+            let range = layout.visible_range(date);
+            if range_results.as_ref().map(|x| x.range) != Some(range) {
+                let results = RangeResults::query(
+                    &SearchDates::new(date, start_date, range, timezone),
+                    &range_search.cmd,
+                    search_shell,
+                )?;
+                if heatmap && heat_cmd.is_none() {
+                    let mut day = range.0;
+                    while day <= range.1 {
+                        heat_counts.insert(day, results.lines(day).len());
+                        day += chrono::Duration::days(1);
+                    }
+                }
+                range_results = Some(results);
+            }
+        }
+        if let Some(highlight_command) = &highlight_command {
+            let range = layout.visible_range(date);
+            if command_highlights.as_ref().map(|x| x.0) != Some(range) {
+                command_highlights = Some((
+                    range,
+                    Highlights::query(
+                        &SearchDates::new(date, start_date, range, timezone),
+                        highlight_command,
+                        search_shell,
+                    )?,
+                ));
+            }
+        }
+        let mut highlights = highlights.clone();
+        highlights.extend(&file_highlights);
+        if let Some(range_results) = &range_results {
+            for day in range_results.by_day.keys() {
+                highlights.add(*day);
+            }
+        }
+        if let Some((_, command_highlights)) = &command_highlights {
+            highlights.extend(command_highlights);
+        }
 
-        let (_used_row, used_col) = write_cal(date, date, &term, cal_col, cal_row, &highlights);
-        cal_col = used_col + 2;
-        let (used_row, used_col) = write_cal(
-            date + chrono::Months::new(1),
-            date,
-            &term,
-            cal_col,
-            cal_row,
-            &highlights,
-        );
-        let used_row = used_row.max(8);
+        if let Some((heat_cmd, parse_count)) = &heat_cmd {
+            fill_heat_counts(
+                layout.visible_range(date),
+                start_date,
+                timezone,
+                heat_cmd,
+                search_shell,
+                *parse_count,
+                &mut heat_counts,
+            )?;
+        }
+        //shades are relative to the busiest visible day, not the busiest day of each month
+        let (first_visible, last_visible) = layout.visible_range(date);
+        let heat_max = heat_counts
+            .iter()
+            .filter(|(day, _)| **day >= first_visible && **day <= last_visible)
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0);
 
-        let str_date = if german {
-            format!("{}{}", title, date.format("%d.%m.%Y %a WN %V"))
-                .trim_start()
-                .to_string()
-        } else {
-            format!("{}{}", title, date.format("%Y-%m-%d %a WN %V"))
-                .trim_start()
-                .to_string()
+        let (used_row, used_col) = match pick {
+            Pick::Day | Pick::Week => {
+                let chosen = if pick == Pick::Week {
+                    week_numbering.week_of(date)
+                } else {
+                    (date, date)
+                };
+                let first_month = layout.first_month(date);
+                layout.scrolled_to = Some(first_month);
+                let mut used_row = 0;
+                let mut used_col = 0;
+                for ii in 0..layout.count {
+                    let (month_row, month_col) = write_cal(
+                        first_month + chrono::Months::new(ii as u32),
+                        chosen,
+                        &term,
+                        (ii % layout.per_row) * (MONTH_WIDTH + MONTH_GAP),
+                        (ii / layout.per_row) * MONTH_HEIGHT,
+                        &highlights,
+                        &heat_counts,
+                        heat_max,
+                        sprints.as_ref(),
+                        week_numbering,
+                        timezone,
+                    );
+                    used_row = used_row.max(month_row);
+                    used_col = used_col.max(month_col);
+                }
+                (used_row.max(layout.rows() * MONTH_HEIGHT - 1), used_col)
+            }
+            Pick::Month => {
+                let mut used_row = 0;
+                let mut used_col = 0;
+                for (ii, year) in (date.year() - 1..=date.year() + 1).enumerate() {
+                    let (grid_row, grid_col) = write_month_grid(
+                        year,
+                        date,
+                        &term,
+                        ii * (MONTH_WIDTH + MONTH_GAP),
+                        0,
+                        &highlights,
+                        timezone,
+                    );
+                    used_row = used_row.max(grid_row);
+                    used_col = used_col.max(grid_col);
+                }
+                (used_row + 1, used_col)
+            }
+            Pick::Quarter => {
+                let mut used_row = 0;
+                let mut used_col = 0;
+                for ii in 0..3 {
+                    let (grid_row, grid_col) = write_quarter_grid(
+                        date + chrono::Months::new(12 * ii) - chrono::Months::new(12),
+                        date,
+                        fiscal_year_start,
+                        &term,
+                        ii as usize * (MONTH_WIDTH + MONTH_GAP),
+                        0,
+                        &highlights,
+                        timezone,
+                    );
+                    used_row = used_row.max(grid_row);
+                    used_col = used_col.max(grid_col);
+                }
+                (used_row + 1, used_col)
+            }
+            Pick::Year => {
+                let (used_row, used_col) =
+                    write_year_grid(date, &term, 0, 0, &highlights, timezone);
+                (used_row + 1, used_col)
+            }
+        };
+
+        let mut str_date = match pick {
+            Pick::Day if german => {
+                format!(
+                    "{} WN {:02}",
+                    date.format("%d.%m.%Y %a"),
+                    week_numbering.week_number(date)
+                )
+            }
+            Pick::Day => format!(
+                "{} WN {:02}",
+                date.format("%Y-%m-%d %a"),
+                week_numbering.week_number(date)
+            ),
+            Pick::Week => {
+                let (monday, sunday) = week_numbering.week_of(date);
+                format!(
+                    "{} WN {:02} ({} - {})",
+                    if german {
+                        date.format("%d.%m.%Y %a")
+                    } else {
+                        date.format("%Y-%m-%d %a")
+                    },
+                    week_numbering.week_number(date),
+                    monday.format("%d.%m."),
+                    sunday.format("%d.%m.")
+                )
+            }
+            Pick::Month => date.format("%Y-%m %B").to_string(),
+            Pick::Quarter => {
+                let quarter = Quarter::of(date, fiscal_year_start);
+                format!(
+                    "{} {} ({} - {})",
+                    date.format("%Y-%m"),
+                    quarter.name(fiscal_year_start),
+                    quarter.start.format("%Y-%m-%d"),
+                    quarter.end.format("%Y-%m-%d")
+                )
+            }
+            Pick::Year => date.format("%Y").to_string(),
         };
-        let centered_date = format!("{:^width$}", str_date, width = used_col);
-        let cursor_offset = centered_date.len() / 2 - str_date.len() / 2 + title.len() - 1;
-        let _ = term.print(used_row, 0, &centered_date);
-        let _ = term.set_cursor(used_row, cursor_column + cursor_offset + 1);
+        if let (Some(sprints), Pick::Day | Pick::Week) = (&sprints, pick) {
+            str_date.push_str(&format!("  {}", sprints.describe(date)));
+        }
+        //where the time starts, relative to the date
+        let time_offset = str_date.width() + 2;
+        if datetime {
+            str_date.push_str(&format!("  {}", time.format(time_format)));
+            if datetime_output != DatetimeOutput::Naive || zones.len() > 1 {
+                let zone_name = zones[zone_index].map(|x| x.name()).unwrap_or("local");
+                let offset = match resolve_datetime(date.and_time(time), zones[zone_index]) {
+                    chrono::LocalResult::Single(x) => x.format("%:z").to_string(),
+                    chrono::LocalResult::Ambiguous(x, y) => {
+                        format!("{} (or {})", x.format("%:z"), y.format("%:z"))
+                    }
+                    chrono::LocalResult::None => "invalid".to_string(),
+                };
+                str_date.push_str(&format!(" {} {}", zone_name, offset));
+            }
+        }
+        //all widths in terminal columns, not bytes - titles may well contain umlauts or emoji
+        let shown_title =
+            truncate_to_width(title.trim_start(), width.saturating_sub(str_date.width()));
+        let str_width = shown_title.width() + str_date.width();
+        let left_pad = used_col.saturating_sub(str_width) / 2;
+        let _ = term.print(
+            used_row,
+            0,
+            &format!("{}{}{}", " ".repeat(left_pad), shown_title, str_date),
+        );
+        let message =
+            message.or_else(|| highlights.labels.get(&date).map(|labels| labels.join(", ")));
+        if let Some(message) = &message {
+            let _ = term.print_with_attr(
+                used_row,
+                left_pad + str_width + 2,
+                truncate_to_width(message, width.saturating_sub(left_pad + str_width + 2)),
+                Attr {
+                    fg: Color::YELLOW,
+                    ..Attr::default()
+                },
+            );
+        }
+        if editing_time {
+            let _ = term.set_cursor(
+                used_row,
+                left_pad + shown_title.width() + time_offset + time_column,
+            );
+        } else {
+            let _ = term.set_cursor(used_row, left_pad + shown_title.width() + cursor_column);
+        }
 
         let used_row = used_row + 1;
 
         if debug {
-            match ev {
-                Event::Key(x) => {
-                    let _ = term.print(used_row, 0, &format!("Key pressed: {:?}", x));
-                }
-                _ => {}
+            if let Event::Key(x) = ev {
+                let _ = term.print(used_row, 0, &format!("Key pressed: {:?}", x));
             }
         }
 
-        if let Some(search_cmd) = &search {
-            let search_result = get_search_results(date, search_cmd, max_results)?;
-            let mut lines = search_result.split('\n').collect::<Vec<_>>();
-            if sort_search {
-                lines.sort();
-            }
-            for (ii, line) in lines.iter().enumerate() {
-                term.print(used_row + ii, 0, &line[..line.len().min(width)])?;
+        //with --fullscreen, the search output gets whatever room the calendar leaves
+        let max_results = if fullscreen {
+            max_results.max(height.saturating_sub(used_row + 1))
+        } else {
+            max_results
+        };
+        let shown_panes = (0..pane_count)
+            .filter(|ii| shown_pane.is_none() || shown_pane == Some(*ii))
+            .collect::<Vec<_>>();
+        if !shown_panes.is_empty() {
+            let pane_width = ((width + 1) / shown_panes.len()).saturating_sub(1);
+            //once one pane has a title, all get one, so the outputs line up
+            let with_titles = pane_count > 1
+                || searches
+                    .iter()
+                    .chain(range_search.iter())
+                    .any(|x| x.name.is_some());
+            for (ii, pane) in shown_panes.into_iter().enumerate() {
+                let (search, search_result) = if pane < searches.len() {
+                    let search = &searches[pane];
+                    let search_result = get_search_results(
+                        &SearchDates::new(date, start_date, layout.visible_range(date), timezone),
+                        &search.cmd,
+                        search_shell,
+                        max_results,
+                        &mut search_cache,
+                    )?;
+                    (search, search_result)
+                } else {
+                    let search = range_search.as_ref().unwrap();
+                    let lines = range_results.as_ref().unwrap().lines(date);
+                    (search, lines[..lines.len().min(max_results)].join("\n"))
+                };
+                let mut lines = search_result.split('\n').collect::<Vec<_>>();
+                if sort_search {
+                    lines.sort();
+                }
+                write_search_pane(
+                    &term,
+                    used_row,
+                    ii * (pane_width + 1),
+                    pane_width,
+                    with_titles.then(|| search.name.as_deref().unwrap_or(&search.cmd)),
+                    &lines,
+                    strip_ansi,
+                )?;
             }
         }
         let _ = term.present();
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_cal(
         date: chrono::NaiveDate,
-        date_chosen: chrono::NaiveDate,
+        (chosen_start, chosen_end): (chrono::NaiveDate, chrono::NaiveDate),
         term: &Term<()>,
         start_col: usize,
         start_row: usize,
-        highlight: &[chrono::NaiveDate],
+        highlight: &Highlights,
+        heat_counts: &HashMap<chrono::NaiveDate, usize>,
+        heat_max: usize,
+        sprints: Option<&Sprints>,
+        week_numbering: WeekNumbering,
+        timezone: Option<chrono_tz::Tz>,
     ) -> (usize, usize) {
-        let cal = calendarize::calendarize_with_offset(date, 1);
-        let header = format!(
-            "{} {} {} {} {} {} {}",
-            "Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun",
-        );
+        let week_col = start_col;
+        let start_col = start_col + WEEK_COLUMN_WIDTH;
+        let week_start = week_numbering.week_start();
+        let cal = calendarize::calendarize_with_offset(date, week_start.num_days_from_sunday());
+        let header_width = 7 * 4 - 1;
         let year_month = format!("{} {}", date.format("%Y"), date.format("%B"),);
-        let year_month = format!("{:^width$}", year_month, width = header.len());
+        let year_month = format!("{:^width$}", year_month, width = header_width);
         let attr_underline = Attr {
             effect: Effect::UNDERLINE,
             ..Attr::default()
@@ -481,43 +2095,66 @@ fn main() -> Result<()> {
             //effect: Effect::DIM,
             ..Attr::default()
         };
+        let attr_week_number = Attr {
+            effect: Effect::DIM,
+            ..Attr::default()
+        };
+        let attr_sprint_start = Attr {
+            fg: Color::MAGENTA,
+            ..Attr::default()
+        };
 
         term.print(start_row, start_col, &year_month).unwrap();
-        term.print(start_row + 1, start_col, &header).unwrap();
-        term.print_with_attr(
-            start_row + 1,
-            start_col + header.len() - 7,
-            &header[header.len() - 7..],
-            attr_weekend,
-        )
-        .unwrap();
-
-        term.print_with_attr(start_row + 1, start_col, "M", attr_underline)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 4, "T", attr_underline)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 8, "W", attr_underline)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 13, "h", attr_underline)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 16, "F", attr_underline)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 20, "S", attr_underline_weekend)
-            .unwrap();
-        term.print_with_attr(start_row + 1, start_col + 25, "u", attr_underline_weekend)
+        term.print_with_attr(start_row + 1, week_col, "Wk", attr_week_number)
             .unwrap();
+        //the day names, and which of their letters is the key to jump to them
+        let day_names = [
+            ("Mon", 0),
+            ("Tue", 0),
+            ("Wed", 0),
+            ("Thu", 1),
+            ("Fri", 0),
+            ("Sat", 0),
+            ("Sun", 1),
+        ];
+        for ii in 0..7 {
+            let weekday = (ii + week_start.num_days_from_monday() as usize) % 7;
+            let (name, key) = day_names[weekday];
+            let (attr, attr_key) = if weekday >= 5 {
+                (attr_weekend, attr_underline_weekend)
+            } else {
+                (Attr::default(), attr_underline)
+            };
+            let col = start_col + ii * 4;
+            term.print_with_attr(start_row + 1, col, name, attr)
+                .unwrap();
+            term.print_with_attr(start_row + 1, col + key, &name[key..=key], attr_key)
+                .unwrap();
+        }
 
-        let today = chrono::Local::now().naive_local().date();
+        let today = today(timezone);
 
         let mut max_col = start_col;
         for (row, days) in cal.iter().enumerate() {
+            if let Some(first_day) = days.iter().find(|ii| **ii > 0) {
+                let first_day =
+                    chrono::NaiveDate::from_ymd_opt(date.year(), date.month(), *first_day).unwrap();
+                term.print_with_attr(
+                    start_row + 2 + row,
+                    week_col,
+                    &format!("{:>2}", week_numbering.week_number(first_day)),
+                    attr_week_number,
+                )
+                .unwrap();
+            }
             let mut col = start_col;
             for ii in days {
                 if *ii > 0 {
                     let mod_date =
                         chrono::NaiveDate::from_ymd_opt(date.year(), date.month(), *ii).unwrap();
-                    let attr = if mod_date == date_chosen {
-                        if today == date {
+                    let chosen = mod_date >= chosen_start && mod_date <= chosen_end;
+                    let attr = if chosen {
+                        if today == mod_date {
                             attr_today_chosen
                         } else {
                             attr_chosen
@@ -539,8 +2176,23 @@ fn main() -> Result<()> {
                     } else {
                         attr_future
                     };
+                    let attr = match highlight.color(&mod_date) {
+                        Some(color) if !chosen => Attr { fg: color, ..attr },
+                        _ => attr,
+                    };
+                    let attr = match heat_counts.get(&mod_date) {
+                        Some(count) if *count > 0 => Attr {
+                            bg: HEAT_COLORS[(count * HEAT_COLORS.len()).div_ceil(heat_max) - 1],
+                            ..attr
+                        },
+                        _ => attr,
+                    };
                     term.print_with_attr(start_row + 2 + row, col, &format!("{:>2}", ii), attr)
                         .unwrap();
+                    if sprints.is_some_and(|x| x.is_start(mod_date)) {
+                        term.print_with_attr(start_row + 2 + row, col - 1, "|", attr_sprint_start)
+                            .unwrap();
+                    }
                 }
                 col += 4;
                 max_col = max_col.max(col)
@@ -549,6 +2201,198 @@ fn main() -> Result<()> {
         (start_row + 2 + cal.len(), max_col)
     }
 
+    /// Twelve months of one year, four per row, for --pick=month
+    fn write_month_grid(
+        year: i32,
+        date_chosen: chrono::NaiveDate,
+        term: &Term<()>,
+        start_col: usize,
+        start_row: usize,
+        highlight: &Highlights,
+        timezone: Option<chrono_tz::Tz>,
+    ) -> (usize, usize) {
+        let today = today(timezone);
+        let width = 4 * 7 - 1;
+        term.print(start_row, start_col, &format!("{:^width$}", year))
+            .unwrap();
+        for month in 1..=12 {
+            let first = chrono::NaiveDate::from_ymd_opt(year, month, 1).unwrap();
+            let last = first + chrono::Months::new(1) - chrono::Duration::days(1);
+            let attr = grid_attr(
+                (first.year(), first.month()) == (date_chosen.year(), date_chosen.month()),
+                today >= first && today <= last,
+                last < today,
+                highlight.colors.keys().any(|x| *x >= first && *x <= last),
+            );
+            let row = start_row + 1 + (month as usize - 1) / 4;
+            let col = start_col + ((month as usize - 1) % 4) * 7;
+            term.print_with_attr(row, col, &first.format("%b").to_string(), attr)
+                .unwrap();
+        }
+        (start_row + 4, start_col + width)
+    }
+
+    /// The four quarters of the fiscal year that date is in, each with its months,
+    /// for --pick=quarter
+    #[allow(clippy::too_many_arguments)]
+    fn write_quarter_grid(
+        date: chrono::NaiveDate,
+        date_chosen: chrono::NaiveDate,
+        fiscal_year_start: u32,
+        term: &Term<()>,
+        start_col: usize,
+        start_row: usize,
+        highlight: &Highlights,
+        timezone: Option<chrono_tz::Tz>,
+    ) -> (usize, usize) {
+        let today = today(timezone);
+        let width = 4 * 7 - 1;
+        let first = Quarter::of(date, fiscal_year_start);
+        let first = Quarter::of(
+            first.start - chrono::Months::new(3 * (first.quarter - 1)),
+            fiscal_year_start,
+        );
+        let chosen = Quarter::of(date_chosen, fiscal_year_start);
+        let year_name = first.name(fiscal_year_start);
+        let year_name = year_name.split('-').next().unwrap();
+        term.print(start_row, start_col, &format!("{:^width$}", year_name))
+            .unwrap();
+        for ii in 0..4 {
+            let quarter = Quarter::of(first.start + chrono::Months::new(3 * ii), fiscal_year_start);
+            let col = start_col + ii as usize * 7;
+            let attr = grid_attr(
+                quarter.start == chosen.start,
+                today >= quarter.start && today <= quarter.end,
+                quarter.end < today,
+                highlight
+                    .colors
+                    .keys()
+                    .any(|x| *x >= quarter.start && *x <= quarter.end),
+            );
+            term.print_with_attr(start_row + 1, col, &format!("Q{}", quarter.quarter), attr)
+                .unwrap();
+            for month in 0..3 {
+                let month_start = quarter.start + chrono::Months::new(month);
+                term.print_with_attr(
+                    start_row + 2 + month as usize,
+                    col,
+                    &month_start.format("%b").to_string(),
+                    if quarter.start == chosen.start {
+                        attr
+                    } else {
+                        Attr::default()
+                    },
+                )
+                .unwrap();
+            }
+        }
+        (start_row + 5, start_col + width)
+    }
+
+    /// YEAR_GRID_YEARS years, four per row, for --pick=year
+    fn write_year_grid(
+        date_chosen: chrono::NaiveDate,
+        term: &Term<()>,
+        start_col: usize,
+        start_row: usize,
+        highlight: &Highlights,
+        timezone: Option<chrono_tz::Tz>,
+    ) -> (usize, usize) {
+        let today = today(timezone);
+        let first_year = date_chosen.year() - date_chosen.year().rem_euclid(YEAR_GRID_YEARS);
+        let width = 4 * 7 - 1;
+        term.print(
+            start_row,
+            start_col,
+            &format!(
+                "{:^width$}",
+                format!("{} - {}", first_year, first_year + YEAR_GRID_YEARS - 1)
+            ),
+        )
+        .unwrap();
+        for ii in 0..YEAR_GRID_YEARS {
+            let year = first_year + ii;
+            let attr = grid_attr(
+                year == date_chosen.year(),
+                year == today.year(),
+                year < today.year(),
+                highlight.colors.keys().any(|x| x.year() == year),
+            );
+            let row = start_row + 1 + ii as usize / 4;
+            let col = start_col + (ii as usize % 4) * 7;
+            term.print_with_attr(row, col, &format!("{:>4}", year), attr)
+                .unwrap();
+        }
+        (
+            start_row + 1 + YEAR_GRID_YEARS as usize / 4,
+            start_col + width,
+        )
+    }
+
+    /// The month / year grid version of write_cal's chosen / today / past / highlight styles
+    fn grid_attr(chosen: bool, current: bool, past: bool, highlighted: bool) -> Attr {
+        let effect = if current {
+            Effect::UNDERLINE
+        } else {
+            Effect::empty()
+        };
+        if chosen {
+            Attr {
+                fg: Color::BLUE,
+                effect: effect | Effect::BOLD,
+                ..Attr::default()
+            }
+        } else if highlighted {
+            Attr {
+                fg: if past { Color::LIGHT_CYAN } else { Color::CYAN },
+                effect,
+                ..Attr::default()
+            }
+        } else if current {
+            Attr {
+                fg: Color::RED,
+                effect,
+                ..Attr::default()
+            }
+        } else if past {
+            Attr {
+                fg: Color::LIGHT_BLACK,
+                ..Attr::default()
+            }
+        } else {
+            Attr::default()
+        }
+    }
+
+    fn add_time_digit(col: &mut usize, digit: u8, time: &mut chrono::NaiveTime, format: &str) {
+        // HH:MM or HH:MM:SS (separators at positions 2 and 5)
+        let str_time = format!("{}", time.format(format));
+        let mut chars: Vec<char> = str_time.chars().collect();
+        chars[*col] = std::char::from_digit(digit as u32, 10).unwrap();
+        let new_str_time: String = chars.into_iter().collect();
+        let mut new_time = chrono::NaiveTime::parse_from_str(&new_str_time, format);
+
+        if new_time.is_err() && ((*col == 0) || (*col == 3) || (*col == 6)) {
+            //2 on 09:00 - 29:00 is invalid, 20:00 is what they'd want
+            let mut chars: Vec<char> = str_time.chars().collect();
+            chars[*col] = std::char::from_digit(digit as u32, 10).unwrap();
+            chars[*col + 1] = '0';
+            let new_str_time: String = chars.into_iter().collect();
+            new_time = chrono::NaiveTime::parse_from_str(&new_str_time, format);
+        }
+
+        if let Ok(new_time) = new_time {
+            *time = new_time;
+            *col += 1;
+            if (*col == 2) || (*col == 5) {
+                *col += 1;
+            }
+            if *col >= str_time.len() {
+                *col = 0;
+            }
+        }
+    }
+
     fn add_digit(col: &mut usize, digit: u8, date: &mut chrono::NaiveDate, german: bool) {
         if german {
             // German format: dd.mm.yyyy (separators at positions 2 and 5)
@@ -605,21 +2449,154 @@ fn main() -> Result<()> {
     Ok(())
 }
 
-fn get_search_results(
-    date: chrono::NaiveDate,
+/// Modification times of the watched files, None for files we can't stat
+fn modification_times(filenames: &[String]) -> Vec<Option<std::time::SystemTime>> {
+    filenames
+        .iter()
+        .map(|x| std::fs::metadata(x).and_then(|x| x.modified()).ok())
+        .collect()
+}
+
+/// The searches currently on screen - all of them, or the one selected with 'p'
+fn shown_searches(searches: &[Search], shown_pane: Option<usize>) -> Vec<&Search> {
+    searches
+        .iter()
+        .enumerate()
+        .filter(|(ii, _)| shown_pane.is_none() || shown_pane == Some(*ii))
+        .map(|(_, search)| search)
+        .collect()
+}
+
+/// Walk from start in step-day increments until one of the searches
+/// (or the --search-range command) has output.
+/// Gives up after limit days. Outputs are cached, repeated n/N presses are cheap.
+#[allow(clippy::too_many_arguments)]
+fn find_next_result(
+    term: &Term<()>,
+    progress_row: usize,
+    start: chrono::NaiveDate,
+    step: i64,
+    limit: usize,
+    default_date: chrono::NaiveDate,
+    timezone: Option<chrono_tz::Tz>,
+    layout: &MonthLayout,
+    searches: &[&Search],
+    range_search: Option<&Search>,
+    search_shell: bool,
+    search_cache: &mut HashMap<(String, chrono::NaiveDate), String>,
+) -> Result<Option<chrono::NaiveDate>> {
+    let mut day = start;
+    //requeried whenever we walk out of the months it covers
+    let mut range_results: Option<RangeResults> = None;
+    for ii in 1..=limit {
+        day = match day.checked_add_signed(chrono::Duration::days(step)) {
+            Some(day) => day,
+            None => return Ok(None),
+        };
+        let _ = term.print(
+            progress_row,
+            0,
+            &format!("searching {} ({}/{})", day.format("%Y-%m-%d"), ii, limit),
+        );
+        let _ = term.present();
+        for search in searches {
+            let output = match search_cache.entry((search.cmd.clone(), day)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(run_search_command(
+                    &SearchDates::new(day, default_date, layout.visible_range(day), timezone),
+                    &search.cmd,
+                    search_shell,
+                    true,
+                )?),
+            };
+            if !output.trim().is_empty() {
+                return Ok(Some(day));
+            }
+        }
+        if let Some(range_search) = range_search {
+            if !range_results
+                .as_ref()
+                .is_some_and(|x| day >= x.range.0 && day <= x.range.1)
+            {
+                range_results = Some(RangeResults::query(
+                    &SearchDates::new(day, default_date, layout.visible_range(day), timezone),
+                    &range_search.cmd,
+                    search_shell,
+                )?);
+            }
+            if range_results
+                .as_ref()
+                .is_some_and(|x| !x.lines(day).is_empty())
+            {
+                return Ok(Some(day));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Print one search's output lines, below a title line if given
+fn write_search_pane(
+    term: &Term<()>,
+    start_row: usize,
+    start_col: usize,
+    width: usize,
+    title: Option<&str>,
+    lines: &[&str],
+    strip_ansi: bool,
+) -> Result<()> {
+    let mut row = start_row;
+    if let Some(title) = title {
+        let attr_title = Attr {
+            effect: Effect::BOLD | Effect::UNDERLINE,
+            ..Attr::default()
+        };
+        term.print_with_attr(row, start_col, truncate_to_width(title, width), attr_title)?;
+        row += 1;
+    }
+    for line in lines {
+        let mut col = 0;
+        for (attr, text) in parse_ansi(line) {
+            let attr = if strip_ansi { Attr::default() } else { attr };
+            let text = truncate_to_width(&text, width.saturating_sub(col));
+            col += term.print_with_attr(row, start_col + col, text, attr)?;
+        }
+        row += 1;
+    }
+    Ok(())
+}
+
+/// Run a search (or highlight / count) command. Without --search-shell and without placeholders,
+/// the date is appended if add_if_not_found.
+fn run_search_command(
+    dates: &SearchDates,
     search_cmd: &str,
-    max_lines: usize,
+    search_shell: bool,
+    add_if_not_found: bool,
 ) -> Result<String> {
-    let search_parsed = parse_to_arguments_replace(
-        search_cmd,
-        Some(&format!("{}", date.format("%Y-%m-%d"))),
-        true,
-    );
+    let search_parsed = if search_shell {
+        shell_arguments_replace(search_cmd, dates)
+    } else {
+        parse_to_arguments_replace(search_cmd, Some(dates), add_if_not_found)
+    };
     let output = std::process::Command::new(&search_parsed[0])
         .args(&search_parsed[1..])
+        .envs(dates.env_vars())
         .output()
         .context("search cmd failed")?;
-    let results = String::from_utf8(output.stdout).context("invalid utf8")?;
+    String::from_utf8(output.stdout).context("invalid utf8")
+}
+
+/// Run the search for display, so always fresh, but remember the output for n/N
+fn get_search_results(
+    dates: &SearchDates,
+    search_cmd: &str,
+    search_shell: bool,
+    max_lines: usize,
+    search_cache: &mut HashMap<(String, chrono::NaiveDate), String>,
+) -> Result<String> {
+    let results = run_search_command(dates, search_cmd, search_shell, true)?;
+    search_cache.insert((search_cmd.to_string(), dates.date), results.clone());
     //cut results to max_lines
     let results = results
         .lines()
@@ -629,3 +2606,302 @@ fn get_search_results(
         .join("\n");
     Ok(results)
 }
+
+/// Run the heat command for every day in the range that we haven't seen yet.
+/// With parse_count, a numeric output is taken as the count, otherwise the
+/// non-empty output lines are counted.
+fn fill_heat_counts(
+    (first, last): (chrono::NaiveDate, chrono::NaiveDate),
+    default_date: chrono::NaiveDate,
+    timezone: Option<chrono_tz::Tz>,
+    heat_cmd: &str,
+    search_shell: bool,
+    parse_count: bool,
+    heat_counts: &mut HashMap<chrono::NaiveDate, usize>,
+) -> Result<()> {
+    let mut day = first;
+    while day <= last {
+        if let Entry::Vacant(entry) = heat_counts.entry(day) {
+            let output = run_search_command(
+                &SearchDates::new(day, default_date, (first, last), timezone),
+                heat_cmd,
+                search_shell,
+                true,
+            )?;
+            let count = match output.trim().parse::<usize>() {
+                Ok(count) if parse_count => count,
+                _ => output.lines().filter(|x| !x.trim().is_empty()).count(),
+            };
+            entry.insert(count);
+        }
+        day += chrono::Duration::days(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(date: &str) -> chrono::NaiveDate {
+        chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    fn search_dates() -> SearchDates {
+        SearchDates::new(
+            ymd("2024-03-05"),
+            ymd("2024-03-01"),
+            (ymd("2024-02-01"), ymd("2024-04-30")),
+            None,
+        )
+    }
+
+    #[test]
+    fn placeholders() {
+        let dates = search_dates();
+        assert_eq!(
+            replace_placeholders("log {} {%d.%m.%Y}", &dates),
+            ("log 2024-03-05 05.03.2024".to_string(), true)
+        );
+        assert_eq!(
+            replace_placeholders("{year}/{month}/{day} W{week} {weekday}", &dates),
+            ("2024/03/05 W10 Tue".to_string(), true)
+        );
+        assert_eq!(
+            replace_placeholders("{range_start}..{range_end}", &dates),
+            ("2024-02-01..2024-04-30".to_string(), true)
+        );
+        //unknown ones, unclosed braces, invalid formats are left alone
+        assert_eq!(
+            replace_placeholders("awk '{print $1}' {", &dates),
+            ("awk '{print $1}' {".to_string(), false)
+        );
+        assert_eq!(
+            replace_placeholders("{%Q}", &dates),
+            ("{%Q}".to_string(), false)
+        );
+        assert_eq!(
+            replace_placeholders("{{}}", &dates),
+            ("{2024-03-05}".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn shell_arguments() {
+        //the date is passed as $1, never appended to the end of a pipeline
+        assert_eq!(
+            shell_arguments_replace("git log | grep fix", &search_dates()),
+            vec![
+                "sh",
+                "-c",
+                "git log | grep fix",
+                "fdate",
+                "2024-03-05",
+                "2024-02-01",
+                "2024-04-30"
+            ]
+        );
+        assert_eq!(
+            parse_to_arguments_replace("grep -r fix", Some(&search_dates()), true),
+            vec!["grep", "-r", "fix", "2024-03-05"]
+        );
+        assert_eq!(
+            parse_to_arguments_replace("grep -r {%Y%m%d} .", Some(&search_dates()), true),
+            vec!["grep", "-r", "20240305", "."]
+        );
+    }
+
+    #[test]
+    fn truncation_by_terminal_width() {
+        assert_eq!(truncate_to_width("Grüße", 3), "Grü");
+        assert_eq!(truncate_to_width("Grüße", 10), "Grüße");
+        //emoji and CJK take two columns - and are never split
+        assert_eq!(truncate_to_width("a😀b", 2), "a");
+        assert_eq!(truncate_to_width("a😀b", 3), "a😀");
+        assert_eq!(truncate_to_width("日本語", 5), "日本");
+        assert_eq!(truncate_to_width("日本語", 0), "");
+        //as tuikit prints them
+        assert_eq!(truncate_to_width("a\x01b", 2), "a");
+    }
+
+    /// The text of parse_ansi's segments, joined
+    fn ansi_text(line: &str) -> String {
+        parse_ansi(line).into_iter().map(|(_, text)| text).collect()
+    }
+
+    #[test]
+    fn ansi_parsing() {
+        assert_eq!(ansi_text("abc\tdef\tg"), "abc     def     g");
+        assert_eq!(ansi_text("\tx"), "        x");
+        //tab stops count columns, not bytes or chars
+        assert_eq!(ansi_text("ü日\tx"), "ü日     x");
+        assert_eq!(ansi_text("\x1b[31mab\x1b[0m\tc"), "ab      c");
+        //SGR is interpreted, other CSI sequences dropped
+        let segments = parse_ansi("\x1b[1;31mred\x1b[0m \x1b[2Kplain");
+        assert_eq!(segments[0].1, "red");
+        assert_eq!(segments[0].0.fg, Color::AnsiValue(1));
+        assert!(segments[0].0.effect.contains(Effect::BOLD));
+        assert_eq!(segments[1].1, " plain");
+        assert_eq!(segments[1].0, Attr::default());
+        //OSC 8 hyperlinks, terminated by BEL or ESC \
+        assert_eq!(
+            ansi_text("\x1b]8;;file:///x\x07link\x1b]8;;\x07 b"),
+            "link b"
+        );
+        assert_eq!(
+            ansi_text("\x1b]8;;file:///y\x1b\\link2\x1b]8;;\x1b\\ c"),
+            "link2 c"
+        );
+        //control characters
+        assert_eq!(ansi_text("a\x01b\r"), "ab");
+    }
+
+    #[test]
+    fn highlight_parsing() {
+        let highlights = Highlights::parse(
+            "2024-03-05\tstandup\twork\n\
+             2024-03-06\n\
+             not a date\tlabel\n\
+             2024-13-01\tno such month\n\
+             \n\
+             2024-03-07\t\twork\n\
+             2024-03-08\tbirthday\thome\n\
+             2024-03-05\tretro\n",
+        );
+        let mut days: Vec<_> = highlights.colors.keys().copied().collect();
+        days.sort();
+        assert_eq!(
+            days,
+            vec![
+                ymd("2024-03-05"),
+                ymd("2024-03-06"),
+                ymd("2024-03-07"),
+                ymd("2024-03-08")
+            ]
+        );
+        assert_eq!(
+            highlights.labels[&ymd("2024-03-05")],
+            vec!["standup", "retro"]
+        );
+        //a day without label or category is just highlighted
+        assert!(highlights.contains(&ymd("2024-03-06")));
+        assert!(!highlights.labels.contains_key(&ymd("2024-03-06")));
+        assert_eq!(highlights.color(&ymd("2024-03-06")), None);
+        assert!(!highlights.labels.contains_key(&ymd("2024-03-07")));
+        //same category, same colour
+        assert!(highlights.color(&ymd("2024-03-05")).is_some());
+        assert_eq!(
+            highlights.color(&ymd("2024-03-05")),
+            highlights.color(&ymd("2024-03-07"))
+        );
+        assert_ne!(
+            highlights.color(&ymd("2024-03-05")),
+            highlights.color(&ymd("2024-03-08"))
+        );
+    }
+
+    #[test]
+    fn week_numbering_schemes() {
+        use WeekNumbering::*;
+        //2024-12-29 is a Sunday, 2025-01-01 a Wednesday
+        assert_eq!(Iso.year_and_week(ymd("2024-12-29")), (2024, 52));
+        assert_eq!(Iso.year_and_week(ymd("2024-12-30")), (2025, 1));
+        assert_eq!(Us.year_and_week(ymd("2024-12-28")), (2024, 52));
+        assert_eq!(Us.year_and_week(ymd("2024-12-29")), (2025, 1));
+        assert_eq!(Us.year_and_week(ymd("2025-01-04")), (2025, 1));
+        assert_eq!(Us.year_and_week(ymd("2025-01-05")), (2025, 2));
+        assert_eq!(Broadcast.year_and_week(ymd("2024-12-29")), (2024, 52));
+        assert_eq!(Broadcast.year_and_week(ymd("2024-12-30")), (2025, 1));
+        assert_eq!(Broadcast.year_and_week(ymd("2025-01-06")), (2025, 2));
+        assert_eq!(Simple.year_and_week(ymd("2025-01-07")), (2025, 1));
+        assert_eq!(Simple.year_and_week(ymd("2025-01-08")), (2025, 2));
+        assert_eq!(Simple.year_and_week(ymd("2024-12-31")), (2024, 53));
+    }
+
+    #[test]
+    fn weeks_match_calendar_rows() {
+        //every day of a week has the same number, and the week starts on the row's first day
+        for numbering in [
+            WeekNumbering::Iso,
+            WeekNumbering::Us,
+            WeekNumbering::Broadcast,
+        ] {
+            let mut day = ymd("2023-12-01");
+            while day < ymd("2026-02-01") {
+                let (first, last) = numbering.week_of(day);
+                assert_eq!(first.weekday(), numbering.week_start());
+                assert_eq!(
+                    numbering.year_and_week(first),
+                    numbering.year_and_week(last),
+                    "{:?} {}",
+                    numbering,
+                    day
+                );
+                day += chrono::Duration::days(1);
+            }
+        }
+        //March 2024: the US week 10 is Sunday the 3rd to Saturday the 9th
+        assert_eq!(
+            WeekNumbering::Us.week_of(ymd("2024-03-03")),
+            (ymd("2024-03-03"), ymd("2024-03-09"))
+        );
+        assert_eq!(
+            WeekNumbering::Us.year_and_week(ymd("2024-03-09")),
+            (2024, 10)
+        );
+    }
+
+    #[test]
+    fn quarters() {
+        let quarter = Quarter::of(ymd("2024-08-15"), 1);
+        assert_eq!(quarter.name(1), "2024-Q3");
+        assert_eq!(
+            (quarter.start, quarter.end),
+            (ymd("2024-07-01"), ymd("2024-09-30"))
+        );
+        let quarter = Quarter::of(ymd("2024-08-15"), 4);
+        assert_eq!(quarter.name(4), "FY25-Q2");
+        assert_eq!(
+            (quarter.start, quarter.end),
+            (ymd("2024-07-01"), ymd("2024-09-30"))
+        );
+        let quarter = Quarter::of(ymd("2025-03-31"), 4);
+        assert_eq!(quarter.name(4), "FY25-Q4");
+        assert_eq!(
+            (quarter.start, quarter.end),
+            (ymd("2025-01-01"), ymd("2025-03-31"))
+        );
+        let quarter = Quarter::of(ymd("2024-10-01"), 10);
+        assert_eq!(quarter.name(10), "FY25-Q1");
+        assert_eq!(quarter.end, ymd("2024-12-31"));
+    }
+
+    #[test]
+    fn sprints() {
+        let sprints = Sprints {
+            anchor: ymd("2023-01-02"),
+            length: 14,
+        };
+        assert_eq!(
+            sprints.of(ymd("2024-08-15")),
+            Some((43, ymd("2024-08-12"), ymd("2024-08-25")))
+        );
+        assert_eq!(
+            sprints.of(ymd("2023-01-01")),
+            Some((0, ymd("2022-12-19"), ymd("2023-01-01")))
+        );
+        assert!(sprints.is_start(ymd("2024-08-12")));
+        assert!(!sprints.is_start(ymd("2024-08-13")));
+        assert_eq!(sprints.describe(ymd("2024-08-15")), "Sprint 43, day 4/10");
+        assert_eq!(sprints.describe(ymd("2024-08-18")), "Sprint 43, day 5/10");
+        assert_eq!(sprints.describe(ymd("2024-08-19")), "Sprint 43, day 6/10");
+        assert_eq!(sprints.of(chrono::NaiveDate::MAX), None);
+        //a sprint starting on a Saturday
+        let sprints = Sprints {
+            anchor: ymd("2024-08-10"),
+            length: 10,
+        };
+        assert_eq!(sprints.describe(ymd("2024-08-11")), "Sprint 1, day 0/6");
+        assert_eq!(sprints.describe(ymd("2024-08-12")), "Sprint 1, day 1/6");
+    }
+}